@@ -0,0 +1,150 @@
+use crate::{
+    CONFIG, MONGODB,
+    cli::TokenCommand,
+    mongodb::{ApiToken, ApiTokenScope},
+    routes::ApiError,
+};
+use anyhow::Result;
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{StatusCode, header::AUTHORIZATION, request::Parts},
+    middleware::{Next, from_fn_with_state},
+    response::Response,
+    routing::MethodRouter,
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, TimeDelta, Utc};
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use tracing::error;
+
+// Makes tokens easy to recognize, like in leaked secret scans
+const API_TOKEN_PREFIX: &str = "flazxiv_";
+
+// The last used date is only updated this often so not every request writes to the database
+const API_TOKEN_LAST_USED_INTERVAL_MINUTES: i64 = 5;
+
+// The scopes of a request, which are set by `authenticate`
+#[derive(Clone, Default, Debug)]
+pub struct Scopes {
+    scopes: Vec<ApiTokenScope>,
+    token_id: Option<String>,
+}
+
+impl Scopes {
+    pub fn has(&self, scope: ApiTokenScope) -> bool {
+        self.scopes.contains(&ApiTokenScope::Admin) || self.scopes.contains(&scope)
+    }
+
+    // None for requests without a token
+    pub fn token_id(&self) -> Option<&str> {
+        self.token_id.as_deref()
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Scopes {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Self>().cloned().unwrap_or_default())
+    }
+}
+
+pub fn hash_api_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// Returns a new ID and token. Only the hash of the token should be stored
+pub fn generate_api_token() -> (String, String) {
+    let id = rand::random::<[u8; 4]>().iter().map(|byte| format!("{byte:02x}")).collect();
+    let token = format!("{API_TOKEN_PREFIX}{}", URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>()));
+
+    (id, token)
+}
+
+// Resolves the bearer token of the request into its scopes. Requests without one can only read, unless that requires a token too
+pub async fn authenticate(mut req: Request, next: Next) -> Result<Response, ApiError> {
+    let token = req.headers().get(AUTHORIZATION).and_then(|value| value.to_str().ok()).and_then(|value| value.strip_prefix("Bearer "));
+
+    let scopes = match token {
+        Some(token) => {
+            let mongodb = MONGODB.get().unwrap();
+
+            let Some(api_token) = mongodb.api_tokens.get_active_by_hash(&hash_api_token(token)).await? else {
+                return Err(ApiError::new(StatusCode::UNAUTHORIZED, "invalid_token", "Invalid API token."));
+            };
+
+            if is_last_used_stale(&api_token)
+                && let Err(error) = mongodb.api_tokens.set_last_used(&api_token.id).await
+            {
+                error!("An error occurred while trying to update API token {}: {error:?}", api_token.id);
+            }
+
+            Scopes { scopes: api_token.scopes, token_id: Some(api_token.id) }
+        },
+        None => {
+            let scopes = if CONFIG.load().require_read_token { vec![] } else { vec![ApiTokenScope::Read] };
+            Scopes { scopes, token_id: None }
+        },
+    };
+
+    req.extensions_mut().insert(scopes);
+
+    Ok(next.run(req).await)
+}
+
+fn is_last_used_stale(api_token: &ApiToken) -> bool {
+    let last_used_at = api_token.last_used_at.as_ref().and_then(|last_used_at| DateTime::parse_from_rfc3339(last_used_at).ok());
+    last_used_at.is_none_or(|last_used_at| Utc::now() - last_used_at.to_utc() > TimeDelta::minutes(API_TOKEN_LAST_USED_INTERVAL_MINUTES))
+}
+
+async fn require_scope(State(scope): State<ApiTokenScope>, req: Request, next: Next) -> Result<Response, ApiError> {
+    let scopes = req.extensions().get::<Scopes>().cloned().unwrap_or_default();
+
+    if !scopes.has(scope) {
+        let message = format!(r#"This route requires an API token with the "{}" scope."#, scope.name());
+
+        return Err(match scopes.token_id.is_some() {
+            true => ApiError::new(StatusCode::FORBIDDEN, "insufficient_scope", message),
+            false => ApiError::new(StatusCode::UNAUTHORIZED, "missing_token", message),
+        });
+    }
+
+    Ok(next.run(req).await)
+}
+
+// Only lets requests with the scope through to the routes
+pub fn require(scope: ApiTokenScope, method_router: MethodRouter) -> MethodRouter {
+    method_router.route_layer(from_fn_with_state(scope, require_scope))
+}
+
+pub async fn run_token_command(command: &TokenCommand) -> Result<()> {
+    let mongodb = MONGODB.get().unwrap();
+
+    match command {
+        TokenCommand::Create { name, scopes } => {
+            let (id, token) = generate_api_token();
+            mongodb.api_tokens.insert(&id, name, &hash_api_token(&token), scopes).await?;
+
+            println!("Created API token {id} ({name}). Store it somewhere safe, as it won't be shown again:\n{token}");
+        },
+        TokenCommand::List => {
+            for api_token in mongodb.api_tokens.find().await? {
+                let scopes = api_token.scopes.iter().map(ApiTokenScope::name).collect::<Vec<&str>>().join(",");
+                let last_used_at = api_token.last_used_at.as_deref().unwrap_or("never");
+                let revoked = api_token.revoked_at.as_ref().map(|revoked_at| format!(" (revoked at {revoked_at})")).unwrap_or_default();
+
+                println!(
+                    "{}\t{}\t{scopes}\tcreated at {}\tlast used {last_used_at}{revoked}",
+                    api_token.id, api_token.name, api_token.created_at
+                );
+            }
+        },
+        TokenCommand::Revoke { id } => match mongodb.api_tokens.revoke(id).await? {
+            true => println!("Revoked API token {id}."),
+            false => println!("There is no active API token with the ID {id}."),
+        },
+    }
+
+    Ok(())
+}
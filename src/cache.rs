@@ -0,0 +1,212 @@
+use crate::{CONFIG, RESPONSE_CACHE, auth::Scopes, mongodb::ApiTokenScope};
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::Request,
+    http::{
+        HeaderMap, HeaderValue, Method, StatusCode,
+        header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, VARY},
+    },
+    middleware::{Next, from_fn},
+    response::{IntoResponse, Response},
+    routing::MethodRouter,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, hash_map::DefaultHasher},
+    hash::{Hash, Hasher},
+    sync::{
+        Mutex,
+        atomic::{AtomicI64, Ordering},
+    },
+    time::Instant,
+};
+use tracing::error;
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+#[derive(Deserialize, Debug)]
+pub struct ResponseCacheConfig {
+    #[serde(default = "ResponseCacheConfig::default_enabled")]
+    pub enabled: bool,
+
+    #[serde(default = "ResponseCacheConfig::default_max_entries")]
+    pub max_entries: usize,
+}
+
+impl ResponseCacheConfig {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    fn default_max_entries() -> usize {
+        1000
+    }
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self { enabled: Self::default_enabled(), max_entries: Self::default_max_entries() }
+    }
+}
+
+#[derive(Debug)]
+struct CachedResponse {
+    revision: i64,
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+    cached_at: Instant,
+}
+
+// Responses of read routes, which stay valid until the next write to the database
+#[derive(Debug)]
+pub struct ResponseCache {
+    // The time of the last write in milliseconds, which ETags and Last-Modified are derived from
+    revision: AtomicI64,
+
+    responses: Mutex<HashMap<String, CachedResponse>>,
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        Self { revision: AtomicI64::new(Utc::now().timestamp() * 1000), responses: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl ResponseCache {
+    pub fn revision(&self) -> i64 {
+        self.revision.load(Ordering::Relaxed)
+    }
+
+    // This should be called after anything that changes what the read routes return
+    pub fn invalidate(&self) {
+        let now = Utc::now().timestamp() * 1000;
+
+        // Every revision gets its own second since Last-Modified isn't more precise than that, and it must always increase
+        let _ = self.revision.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |revision| Some(now.max(revision + 1000)));
+        self.responses.lock().unwrap().clear();
+    }
+
+    fn get(&self, key: &str, revision: i64) -> Option<(Option<HeaderValue>, Bytes)> {
+        let responses = self.responses.lock().unwrap();
+        let response = responses.get(key).filter(|response| response.revision == revision)?;
+
+        Some((response.content_type.clone(), response.body.clone()))
+    }
+
+    fn insert(&self, key: String, revision: i64, content_type: Option<HeaderValue>, body: Bytes, max_entries: usize) {
+        let mut responses = self.responses.lock().unwrap();
+
+        // Make room by dropping the oldest response
+        if responses.len() >= max_entries
+            && let Some(oldest_key) = responses.iter().min_by_key(|(_, response)| response.cached_at).map(|(key, _)| key.clone())
+        {
+            responses.remove(&oldest_key);
+        }
+
+        responses.insert(key, CachedResponse { revision, content_type, body, cached_at: Instant::now() });
+    }
+}
+
+async fn cache(req: Request, next: Next) -> Response {
+    if req.method() != Method::GET {
+        return next.run(req).await;
+    }
+
+    let query = req.uri().query().unwrap_or_default();
+
+    // A random order without a seed is supposed to be different every time
+    if query.contains("sort_by=random") && !query.contains("seed=") {
+        return next.run(req).await;
+    }
+
+    // Private bookmarks change what's returned, so they're cached separately
+    let include_private = req.extensions().get::<Scopes>().is_some_and(|scopes| scopes.has(ApiTokenScope::ReadPrivate));
+    let key = format!("{include_private}:{}?{query}", req.uri().path());
+    let revision = RESPONSE_CACHE.revision();
+
+    let etag = {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        format!(r#""{revision:x}-{:x}""#, hasher.finish())
+    };
+
+    let last_modified = DateTime::from_timestamp_millis(revision).unwrap_or_default();
+
+    if is_not_modified(req.headers(), &etag, last_modified) {
+        return with_cache_headers(StatusCode::NOT_MODIFIED.into_response(), &etag, last_modified);
+    }
+
+    let config = CONFIG.load();
+
+    if config.response_cache.enabled
+        && let Some((content_type, body)) = RESPONSE_CACHE.get(&key, revision)
+    {
+        let mut response = Body::from(body).into_response();
+
+        if let Some(content_type) = content_type {
+            response.headers_mut().insert(CONTENT_TYPE, content_type);
+        }
+
+        return with_cache_headers(response, &etag, last_modified);
+    }
+
+    let response = next.run(req).await;
+
+    // Errors aren't cached, and the response is stale if something was written while it was being made
+    if response.status() != StatusCode::OK || RESPONSE_CACHE.revision() != revision {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(error) => {
+            error!("An error occurred while trying to read a response to cache: {error:?}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        },
+    };
+
+    if config.response_cache.enabled {
+        RESPONSE_CACHE.insert(key, revision, parts.headers.get(CONTENT_TYPE).cloned(), body.clone(), config.response_cache.max_entries);
+    }
+
+    with_cache_headers(Response::from_parts(parts, Body::from(body)), &etag, last_modified)
+}
+
+// If-Modified-Since is only used when there's no If-None-Match
+fn is_not_modified(headers: &HeaderMap, etag: &str, last_modified: DateTime<Utc>) -> bool {
+    if let Some(if_none_match) = headers.get(IF_NONE_MATCH).and_then(|value| value.to_str().ok()) {
+        return if_none_match.split(',').map(|tag| tag.trim().trim_start_matches("W/")).any(|tag| tag == "*" || tag == etag);
+    }
+
+    headers
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        .is_some_and(|if_modified_since| last_modified.timestamp() <= if_modified_since.timestamp())
+}
+
+fn with_cache_headers(mut response: Response, etag: &str, last_modified: DateTime<Utc>) -> Response {
+    let headers = response.headers_mut();
+
+    if let Ok(etag) = HeaderValue::from_str(etag) {
+        headers.insert(ETAG, etag);
+    }
+
+    if let Ok(last_modified) = HeaderValue::from_str(&last_modified.format(HTTP_DATE_FORMAT).to_string()) {
+        headers.insert(LAST_MODIFIED, last_modified);
+    }
+
+    // Clients should always revalidate, which is cheap with the ETag
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    headers.insert(VARY, HeaderValue::from_static("Authorization"));
+
+    response
+}
+
+// Caches the responses of the route and answers conditional requests with 304
+pub fn cached(method_router: MethodRouter) -> MethodRouter {
+    method_router.route_layer(from_fn(cache))
+}
@@ -0,0 +1,41 @@
+use crate::mongodb::ApiTokenScope;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to the config file, which is watched for changes while running
+    #[arg(long, env = "FLAZXIV_CONFIG", default_value = "config.toml")]
+    pub config: PathBuf,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Manage API tokens instead of running the server
+    #[command(subcommand)]
+    Token(TokenCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TokenCommand {
+    /// Create a token, which is only shown once
+    Create {
+        /// A name to recognize the token by
+        #[arg(long)]
+        name: String,
+
+        /// A scope to grant, which can be repeated
+        #[arg(long = "scope", required = true)]
+        scopes: Vec<ApiTokenScope>,
+    },
+
+    /// List all tokens, including revoked ones
+    List,
+
+    /// Revoke a token by its ID
+    Revoke { id: String },
+}
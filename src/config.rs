@@ -1,36 +1,193 @@
-use anyhow::Result;
+use crate::{
+    CLI, CONFIG, MONGODB, RESPONSE_CACHE, cache::ResponseCacheConfig, cli::Command, health::HealthConfig, rate_limit::RateLimits,
+    rules::UserTagRule, server::Server,
+};
+use anyhow::{Context, Result, bail};
 use serde::{
     Deserialize, Deserializer,
     de::{SeqAccess, Visitor},
 };
 use std::{
     collections::HashMap,
+    env::var,
     fmt::{Debug, Display, Formatter, Result as FmtResult},
-    fs::read_to_string,
+    fs::{metadata, read_to_string},
+    sync::Arc,
+    time::Duration,
 };
+use tokio::time::sleep;
 use toml::from_str;
-use tracing::info;
+use tracing::{error, info, warn};
+
+const WATCH_CONFIG_INTERVAL: Duration = Duration::from_secs(5);
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
+    // These can be left out if they're set with the environment variables instead, which `validate` checks
+    #[serde(default)]
     pub pixiv_user_id: u32,
+
+    #[serde(default)]
     pub pixiv_phpsessid: SensitiveString,
+
+    #[serde(default)]
     pub mongodb_uri: SensitiveString,
 
+    #[serde(default)]
+    pub server: Server,
+
+    // API tokens are created with the `token` subcommand. Reading is allowed without a token unless this is set
+    #[serde(default)]
+    pub require_read_token: bool,
+
+    #[serde(default)]
+    pub rate_limits: RateLimits,
+
+    #[serde(default)]
+    pub response_cache: ResponseCacheConfig,
+
+    #[serde(default)]
+    pub health: HealthConfig,
+
+    // These are only used to seed the tag mappings in the database
     #[serde(default, deserialize_with = "deserialize_bookmark_tag_mappings")]
     pub bookmark_tag_mappings: HashMap<String, Vec<String>>,
+
+    #[serde(default)]
+    pub user_tag_rules: Vec<UserTagRule>,
+
+    // The old single write token, which was replaced by scoped API tokens. Only kept to refuse configs still relying on it
+    #[serde(default)]
+    api_token: Option<SensitiveString>,
 }
 
 impl Config {
     pub fn load() -> Result<Self> {
-        let config_string = read_to_string("config.toml")?;
-        let config = from_str(&config_string)?;
+        let config_string = read_to_string(&CLI.config).with_context(|| format!("Could not read {}", CLI.config.display()))?;
+        let mut config = from_str::<Self>(&config_string)?;
+
+        // Secrets can be kept out of the config file
+        if let Ok(pixiv_user_id) = var("FLAZXIV_PIXIV_USER_ID") {
+            config.pixiv_user_id = pixiv_user_id.parse().context("FLAZXIV_PIXIV_USER_ID is not a valid user ID")?;
+        }
+
+        if let Ok(pixiv_phpsessid) = var("FLAZXIV_PIXIV_PHPSESSID") {
+            config.pixiv_phpsessid = SensitiveString(pixiv_phpsessid);
+        }
+
+        if let Ok(mongodb_uri) = var("FLAZXIV_MONGODB_URI") {
+            config.mongodb_uri = SensitiveString(mongodb_uri);
+        }
+
+        config.validate()?;
         info!("Successfully loaded config: {config:#?}");
+
         Ok(config)
     }
+
+    fn validate(&self) -> Result<()> {
+        // Ignoring it would silently leave the write routes to the anonymous defaults. The token subcommand still runs so the replacement can be created
+        let is_token_command = matches!(CLI.command, Some(Command::Token(_)));
+        if !is_token_command && (self.api_token.is_some() || var("FLAZXIV_API_TOKEN").is_ok()) {
+            bail!(
+                "api_token and FLAZXIV_API_TOKEN are no longer supported, create a scoped API token with `flazxiv token create --name <name> --scope write` and remove them"
+            );
+        }
+
+        if self.pixiv_user_id == 0 {
+            bail!("pixiv_user_id must be set");
+        }
+
+        if self.pixiv_phpsessid.0.is_empty() {
+            bail!("pixiv_phpsessid must be set");
+        }
+
+        if self.mongodb_uri.0.is_empty() {
+            bail!("mongodb_uri must be set");
+        }
+
+        if self.server.tls_cert.is_some() != self.server.tls_key.is_some() {
+            bail!("server.tls_cert and server.tls_key must be set together");
+        }
+
+        if let Some(algorithm) = self.server.compression.iter().find(|algorithm| !["gzip", "br", "zstd"].contains(&algorithm.as_str())) {
+            bail!(r#"Unknown compression algorithm "{algorithm}""#);
+        }
+
+        if self.server.request_timeout_seconds == 0 {
+            bail!("server.request_timeout_seconds must be at least 1");
+        }
+
+        for (class, quota) in [("read", &self.rate_limits.read), ("write", &self.rate_limits.write), ("pixiv", &self.rate_limits.pixiv)] {
+            if quota.requests == 0 || quota.per_seconds == 0 {
+                bail!("rate_limits.{class} must allow at least one request per at least one second");
+            }
+        }
+
+        for (tag, pixiv_tags) in &self.bookmark_tag_mappings {
+            if tag.is_empty() || pixiv_tags.is_empty() {
+                bail!(r#"Bookmark tag mapping "{tag}" must have a name and at least one pixiv tag"#);
+            }
+        }
+
+        for rule in &self.user_tag_rules {
+            if rule.user_tag.is_empty() {
+                bail!("Every user tag rule must have a user tag");
+            }
+
+            if rule.mapping.is_none() && rule.any_tags.is_empty() && rule.all_tags.is_empty() {
+                bail!(r#"User tag rule "{}" must have a mapping, any_tags or all_tags"#, rule.user_tag);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Reloads the config whenever the file changes. Invalid configs are rejected and the current one is kept
+pub async fn watch_config() {
+    let modified = || metadata(&CLI.config).and_then(|metadata| metadata.modified()).ok();
+    let mut last_modified = modified();
+
+    loop {
+        sleep(WATCH_CONFIG_INTERVAL).await;
+
+        let current_modified = modified();
+
+        if current_modified == last_modified {
+            continue;
+        }
+
+        last_modified = current_modified;
+        info!("Config file changed. Reloading...");
+
+        match Config::load() {
+            Ok(config) => {
+                if config.mongodb_uri.0 != CONFIG.load().mongodb_uri.0 {
+                    warn!("mongodb_uri was changed, but this requires a restart to take effect.");
+                }
+
+                if config.server != CONFIG.load().server {
+                    warn!("server was changed, but this requires a restart to take effect.");
+                }
+
+                // New mappings from the config are added to the database, while existing ones are kept as they might have been edited
+                if let Some(mongodb) = MONGODB.get()
+                    && let Err(error) = mongodb.tag_mappings.seed(&config.bookmark_tag_mappings).await
+                {
+                    error!("An error occurred while trying to seed tag mappings: {error:?}");
+                }
+
+                RESPONSE_CACHE.invalidate();
+
+                CONFIG.store(Arc::new(config));
+            },
+            Err(error) => error!("The new config is invalid and was not applied: {error:?}"),
+        }
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 pub struct SensitiveString(String);
 
 impl Display for SensitiveString {
@@ -0,0 +1,182 @@
+use crate::{CONFIG, METRICS, MONGODB, pixiv::get_csrf_token};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::atomic::{AtomicBool, AtomicI64, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use tracing::error;
+
+#[derive(Deserialize, Debug)]
+pub struct HealthConfig {
+    // Readiness fails if bookmarks haven't been synced successfully for this long
+    #[serde(default = "HealthConfig::default_max_sync_age_seconds")]
+    pub max_sync_age_seconds: u64,
+
+    // The pixiv session is only checked this often, since every check is a request to pixiv
+    #[serde(default = "HealthConfig::default_pixiv_session_check_interval_seconds")]
+    pub pixiv_session_check_interval_seconds: u64,
+}
+
+impl HealthConfig {
+    fn default_max_sync_age_seconds() -> u64 {
+        15 * 60
+    }
+
+    fn default_pixiv_session_check_interval_seconds() -> u64 {
+        10 * 60
+    }
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            max_sync_age_seconds: Self::default_max_sync_age_seconds(),
+            pixiv_session_check_interval_seconds: Self::default_pixiv_session_check_interval_seconds(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Health {
+    // Syncing hasn't failed for too long if it's been running for less than the maximum age
+    started_at: DateTime<Utc>,
+
+    // The first import of a large library can take much longer than the maximum age, so every imported page counts as a sync
+    importing: AtomicBool,
+    import_progressed_at: AtomicI64,
+
+    // A lock so concurrent probes wait for the same check instead of all requesting pixiv
+    pixiv_session: Mutex<Option<PixivSessionCheck>>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            started_at: Utc::now(),
+            importing: AtomicBool::new(false),
+            import_progressed_at: AtomicI64::new(0),
+            pixiv_session: Mutex::new(None),
+        }
+    }
+}
+
+impl Health {
+    pub fn set_importing(&self, importing: bool) {
+        self.importing.store(importing, Ordering::Relaxed);
+    }
+
+    pub fn record_import_progress(&self) {
+        self.import_progressed_at.store(Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    pub async fn readiness(&self) -> Readiness {
+        let database = self.check_database().await;
+        let pixiv_session = self.check_pixiv_session().await;
+        let sync = self.check_sync();
+
+        Readiness { ready: database.ok && pixiv_session.ok && sync.ok, database, pixiv_session, sync }
+    }
+
+    async fn check_database(&self) -> DatabaseCheck {
+        let start = Instant::now();
+
+        match MONGODB.get().unwrap().ping().await {
+            Ok(()) => DatabaseCheck { ok: true, latency_ms: Some(start.elapsed().as_millis() as u64), error: None },
+            Err(error) => {
+                error!("An error occurred while trying to ping the database: {error:?}");
+                DatabaseCheck { ok: false, latency_ms: None, error: Some("The database could not be pinged.".into()) }
+            },
+        }
+    }
+
+    // pixiv only embeds the CSRF token in its pages for logged-in sessions
+    async fn check_pixiv_session(&self) -> PixivSessionCheck {
+        let interval = Duration::from_secs(CONFIG.load().health.pixiv_session_check_interval_seconds);
+        let mut pixiv_session = self.pixiv_session.lock().await;
+
+        if let Some(check) = pixiv_session.as_ref()
+            && check.checked.elapsed() < interval
+        {
+            return check.clone();
+        }
+
+        let check = match get_csrf_token().await {
+            Ok(_) => PixivSessionCheck { ok: true, checked_at: Utc::now().to_rfc3339(), error: None, checked: Instant::now() },
+            Err(error) => {
+                error!("An error occurred while trying to check the pixiv session: {error:?}");
+                PixivSessionCheck {
+                    ok: false,
+                    checked_at: Utc::now().to_rfc3339(),
+                    error: Some("The pixiv session is invalid or pixiv could not be reached.".into()),
+                    checked: Instant::now(),
+                }
+            },
+        };
+
+        *pixiv_session = Some(check.clone());
+        check
+    }
+
+    fn check_sync(&self) -> SyncCheck {
+        let max_age_seconds = CONFIG.load().health.max_sync_age_seconds;
+        let last_successful_sync_at = DateTime::from_timestamp(METRICS.last_successful_sync.get(), 0).filter(|at| at.timestamp() != 0);
+        let import_progressed_at = DateTime::from_timestamp(self.import_progressed_at.load(Ordering::Relaxed), 0);
+        let synced_at = [last_successful_sync_at, import_progressed_at].into_iter().flatten().fold(self.started_at, DateTime::max);
+        let age_seconds = (Utc::now() - synced_at).num_seconds();
+
+        SyncCheck {
+            ok: age_seconds <= max_age_seconds as i64,
+            importing: self.importing.load(Ordering::Relaxed),
+            last_successful_sync_at: last_successful_sync_at.map(|at| at.to_rfc3339()),
+            age_seconds,
+            max_age_seconds,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Readiness {
+    pub ready: bool,
+    database: DatabaseCheck,
+    pixiv_session: PixivSessionCheck,
+    sync: SyncCheck,
+}
+
+#[derive(Serialize, Debug)]
+struct DatabaseCheck {
+    ok: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct PixivSessionCheck {
+    ok: bool,
+    checked_at: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    #[serde(skip)]
+    checked: Instant,
+}
+
+#[derive(Serialize, Debug)]
+struct SyncCheck {
+    ok: bool,
+
+    // Whether all bookmarks are being inserted into an empty database, where the age is counted from the last inserted page
+    importing: bool,
+
+    // This is null until the first successful sync, and the age is counted from startup until then
+    last_successful_sync_at: Option<String>,
+
+    age_seconds: i64,
+    max_age_seconds: u64,
+}
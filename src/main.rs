@@ -1,40 +1,165 @@
+mod auth;
+mod cache;
+mod cli;
 mod config;
+mod health;
+mod metrics;
 mod mongodb;
 mod pixiv;
+mod rate_limit;
 mod routes;
+mod rules;
+mod search;
+mod server;
 mod sync;
 
 use anyhow::Result;
-use axum::{Router, routing::get, serve};
-use config::Config;
-use mongodb::MongoDB;
+use arc_swap::ArcSwap;
+use auth::{authenticate, require, run_token_command};
+use axum::{
+    Router,
+    middleware::from_fn,
+    routing::{get, patch, post, put},
+};
+use cache::{ResponseCache, cached};
+use clap::Parser;
+use cli::{Cli, Command};
+use config::{Config, watch_config};
+use health::Health;
+use metrics::{Metrics, track_requests};
+use mongodb::{ApiTokenScope, MongoDB};
+use rate_limit::{RateLimitClass, RateLimiter, clean_rate_limits, limit};
 use reqwest::Client;
-use std::sync::{LazyLock, OnceLock};
-use sync::sync_bookmarks;
-use tokio::{main, net::TcpListener, spawn};
+use server::{serve_app, with_middleware};
+use std::{
+    sync::{LazyLock, OnceLock},
+    time::Duration,
+};
+use sync::{index_bookmark_tags, process_tag_translation_queue, sync_bookmarks, sync_user_tags, validate_bookmarks};
+use tokio::{main, spawn, time::timeout};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, warn};
 use tracing_subscriber::fmt;
 
-pub static CONFIG: LazyLock<Config> = LazyLock::new(|| Config::load().expect("Could not load config"));
+pub static CLI: LazyLock<Cli> = LazyLock::new(Cli::parse);
+pub static CONFIG: LazyLock<ArcSwap<Config>> = LazyLock::new(|| ArcSwap::from_pointee(Config::load().expect("Could not load config")));
 pub static MONGODB: OnceLock<MongoDB> = OnceLock::new();
 pub static REQWEST: LazyLock<Client> = LazyLock::new(Client::new);
+pub static HEALTH: LazyLock<Health> = LazyLock::new(Health::default);
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+pub static RATE_LIMITER: LazyLock<RateLimiter> = LazyLock::new(RateLimiter::default);
+pub static RESPONSE_CACHE: LazyLock<ResponseCache> = LazyLock::new(ResponseCache::default);
+pub static SHUTDOWN: LazyLock<CancellationToken> = LazyLock::new(CancellationToken::new);
 pub const USER_AGENT: &str =
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/128.0.0.0 Safari/537.36";
+const SYNC_BOOKMARKS_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[main]
 async fn main() -> Result<()> {
     fmt::init();
+    LazyLock::force(&CONFIG);
+    // The sync age is counted from here until the first successful sync
+    LazyLock::force(&HEALTH);
 
     MONGODB.set(MongoDB::new().await?).expect("Could not set MongoDB");
-    spawn(sync_bookmarks());
 
+    if let Some(Command::Token(command)) = &CLI.command {
+        return run_token_command(command).await;
+    }
+
+    if let Err(error) = MONGODB.get().unwrap().create_indexes().await {
+        error!("An error occurred while trying to create indexes: {error:?}");
+    }
+
+    if let Err(error) = MONGODB.get().unwrap().tag_mappings.seed(&CONFIG.load().bookmark_tag_mappings).await {
+        error!("An error occurred while trying to seed tag mappings: {error:?}");
+    }
+
+    let sync_bookmarks = spawn(sync_bookmarks());
+    spawn(sync_user_tags());
+    spawn(process_tag_translation_queue());
+    spawn(index_bookmark_tags());
+    spawn(validate_bookmarks());
+    spawn(watch_config());
+    spawn(clean_rate_limits());
+
+    // Admin routes aren't rate limited since admin tokens are exempt anyway
     let app = Router::new()
-        .route("/api/bookmark-tags", get(routes::bookmark_tags::handler))
-        .route("/api/bookmarks", get(routes::bookmarks::handler))
-        .route("/api/bookmarks/{id}/validate", get(routes::bookmarks_validate::handler));
+        .route(
+            "/api/admin/bookmark-tags/retranslate",
+            require(ApiTokenScope::Admin, post(routes::admin_bookmark_tags_retranslate::handler)),
+        )
+        .route("/api/admin/bookmark-tags/{id}/history", require(ApiTokenScope::Admin, get(routes::admin_bookmark_tags_history::handler)))
+        .route("/api/admin/bookmark-tags/{id}/locked", require(ApiTokenScope::Admin, put(routes::admin_bookmark_tags_locked::handler)))
+        .route("/api/admin/bookmark-tags/{id}/name", require(ApiTokenScope::Admin, put(routes::admin_bookmark_tags_name::handler)))
+        .route("/api/admin/tag-queue", require(ApiTokenScope::Admin, get(routes::admin_tag_queue::handler)))
+        .route("/api/admin/user-tag-rules/apply", require(ApiTokenScope::Admin, post(routes::admin_user_tag_rules::handler)))
+        .route("/api/bookmark-tags", require(ApiTokenScope::Read, limit(RateLimitClass::Read, cached(get(routes::bookmark_tags::handler)))))
+        .route(
+            "/api/bookmark-tags/autocomplete",
+            require(ApiTokenScope::Read, limit(RateLimitClass::Read, cached(get(routes::bookmark_tags_autocomplete::handler)))),
+        )
+        .route(
+            "/api/bookmark-tags/co-occurrences",
+            require(ApiTokenScope::Read, limit(RateLimitClass::Read, cached(get(routes::bookmark_tags_co_occurrences::handler)))),
+        )
+        .route(
+            "/api/bookmark-tags/{id}/related",
+            require(ApiTokenScope::Read, limit(RateLimitClass::Read, cached(get(routes::bookmark_tags_related::handler)))),
+        )
+        .route("/api/bookmarks", require(ApiTokenScope::Read, limit(RateLimitClass::Read, cached(get(routes::bookmarks::handler)))))
+        .route("/api/bookmarks/lookup", require(ApiTokenScope::Read, limit(RateLimitClass::Read, post(routes::bookmarks_lookup::handler))))
+        .route(
+            "/api/bookmarks/validate",
+            require(ApiTokenScope::Validate, limit(RateLimitClass::Pixiv, post(routes::bookmarks_validate_batch::handler))),
+        )
+        .route(
+            "/api/bookmarks/{id}",
+            require(ApiTokenScope::Read, limit(RateLimitClass::Read, cached(get(routes::bookmarks_get::handler)))).merge(require(
+                ApiTokenScope::Write,
+                limit(RateLimitClass::Pixiv, post(routes::bookmarks_add::handler).delete(routes::bookmarks_delete::handler)),
+            )),
+        )
+        .route(
+            "/api/bookmarks/{id}/user-tags",
+            require(ApiTokenScope::Write, limit(RateLimitClass::Pixiv, patch(routes::bookmarks_user_tags::handler))),
+        )
+        .route(
+            "/api/bookmarks/{id}/validate",
+            require(ApiTokenScope::Validate, limit(RateLimitClass::Pixiv, get(routes::bookmarks_validate::handler))),
+        )
+        .route("/api/docs", get(routes::docs::handler))
+        .route("/api/openapi.json", get(routes::openapi::handler))
+        .route("/api/tag-mappings", require(ApiTokenScope::Read, limit(RateLimitClass::Read, cached(get(routes::tag_mappings::handler)))))
+        .route(
+            "/api/tag-mappings/{name}",
+            require(ApiTokenScope::Read, limit(RateLimitClass::Read, cached(get(routes::tag_mappings_get::handler)))).merge(require(
+                ApiTokenScope::Write,
+                limit(RateLimitClass::Write, put(routes::tag_mappings_put::handler).delete(routes::tag_mappings_delete::handler)),
+            )),
+        )
+        .route("/api/v1/bookmarks", require(ApiTokenScope::Read, limit(RateLimitClass::Read, cached(get(routes::v1_bookmarks::handler)))))
+        .route(
+            "/api/v1/bookmarks/lookup",
+            require(ApiTokenScope::Read, limit(RateLimitClass::Read, post(routes::v1_bookmarks_lookup::handler))),
+        )
+        .route(
+            "/api/v1/bookmarks/{id}",
+            require(ApiTokenScope::Read, limit(RateLimitClass::Read, cached(get(routes::v1_bookmarks_get::handler)))),
+        )
+        .route("/healthz", get(routes::healthz::handler))
+        .route("/metrics", get(routes::metrics::handler))
+        .route("/readyz", get(routes::readyz::handler))
+        .fallback(routes::not_found)
+        .layer(from_fn(authenticate))
+        .layer(from_fn(track_requests));
 
-    let listener = TcpListener::bind("0.0.0.0:3000").await.unwrap();
+    serve_app(with_middleware(app)).await?;
 
-    serve(listener, app).await?;
+    // Let the bookmark sync finish what it's doing so it doesn't stop in the middle of writing
+    if timeout(SYNC_BOOKMARKS_SHUTDOWN_TIMEOUT, sync_bookmarks).await.is_err() {
+        warn!("Bookmark sync did not stop in time.");
+    }
 
     Ok(())
 }
@@ -0,0 +1,101 @@
+use crate::METRICS;
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use mongodb::event::command::CommandEvent;
+use prometheus::{HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
+use std::time::Instant;
+
+#[derive(Debug)]
+pub struct Metrics {
+    pub registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub pixiv_requests: IntCounterVec,
+    pub sync_iterations: IntCounter,
+    pub sync_pages_scanned: IntCounter,
+    pub bookmarks_inserted: IntCounter,
+    pub bookmarks_deleted: IntCounter,
+    pub tag_translation_queue_jobs: IntGaugeVec,
+    pub last_successful_sync: IntGauge,
+    pub mongodb_command_duration: HistogramVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let registry = Registry::new_custom(Some("flazxiv".into()), None).expect("Could not create metrics registry");
+
+        let http_requests =
+            IntCounterVec::new(Opts::new("http_requests_total", "HTTP requests by route and status"), &["method", "route", "status"])
+                .unwrap();
+        let http_request_duration =
+            HistogramVec::new(HistogramOpts::new("http_request_duration_seconds", "HTTP request latencies by route"), &["method", "route"])
+                .unwrap();
+        let pixiv_requests = IntCounterVec::new(Opts::new("pixiv_requests_total", "Requests to pixiv by status"), &["status"]).unwrap();
+        let sync_iterations = IntCounter::new("sync_iterations_total", "Iterations of the bookmark sync loop").unwrap();
+        let sync_pages_scanned = IntCounter::new("sync_pages_scanned_total", "Bookmark pages fetched by the sync loop").unwrap();
+        let bookmarks_inserted = IntCounter::new("bookmarks_inserted_total", "Bookmarks inserted into the local database").unwrap();
+        let bookmarks_deleted = IntCounter::new("bookmarks_deleted_total", "Bookmarks deleted from the local database").unwrap();
+        let tag_translation_queue_jobs =
+            IntGaugeVec::new(Opts::new("tag_translation_queue_jobs", "Tag translation jobs by status"), &["status"]).unwrap();
+        let last_successful_sync =
+            IntGauge::new("last_successful_sync_timestamp_seconds", "When the bookmark sync last finished without errors").unwrap();
+        let mongodb_command_duration = HistogramVec::new(
+            HistogramOpts::new("mongodb_command_duration_seconds", "MongoDB command latencies")
+                .buckets(vec![0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1., 2.5]),
+            &["command", "outcome"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry.register(Box::new(http_request_duration.clone())).unwrap();
+        registry.register(Box::new(pixiv_requests.clone())).unwrap();
+        registry.register(Box::new(sync_iterations.clone())).unwrap();
+        registry.register(Box::new(sync_pages_scanned.clone())).unwrap();
+        registry.register(Box::new(bookmarks_inserted.clone())).unwrap();
+        registry.register(Box::new(bookmarks_deleted.clone())).unwrap();
+        registry.register(Box::new(tag_translation_queue_jobs.clone())).unwrap();
+        registry.register(Box::new(last_successful_sync.clone())).unwrap();
+        registry.register(Box::new(mongodb_command_duration.clone())).unwrap();
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            pixiv_requests,
+            sync_iterations,
+            sync_pages_scanned,
+            bookmarks_inserted,
+            bookmarks_deleted,
+            tag_translation_queue_jobs,
+            last_successful_sync,
+            mongodb_command_duration,
+        }
+    }
+}
+
+// Records the request by its route pattern rather than its path, so IDs don't create a new series each
+pub async fn track_requests(req: Request, next: Next) -> Response {
+    let method = req.method().to_string();
+    let route = req.extensions().get::<MatchedPath>().map(|path| path.as_str().to_string()).unwrap_or_else(|| "unmatched".into());
+    let start = Instant::now();
+
+    let response = next.run(req).await;
+
+    METRICS.http_requests.with_label_values(&[&method, &route, response.status().as_str()]).inc();
+    METRICS.http_request_duration.with_label_values(&[&method, &route]).observe(start.elapsed().as_secs_f64());
+
+    response
+}
+
+pub fn track_mongodb_command(event: CommandEvent) {
+    let (command, outcome, duration) = match event {
+        CommandEvent::Succeeded(event) => (event.command_name, "succeeded", event.duration),
+        CommandEvent::Failed(event) => (event.command_name, "failed", event.duration),
+        _ => return,
+    };
+
+    METRICS.mongodb_command_duration.with_label_values(&[&command, outcome]).observe(duration.as_secs_f64());
+}
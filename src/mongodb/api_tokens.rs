@@ -0,0 +1,55 @@
+use crate::mongodb::{ApiToken, ApiTokenScope};
+use anyhow::Result;
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{Collection, bson::doc};
+
+#[derive(Debug)]
+pub struct ApiTokens {
+    collection: Collection<ApiToken>,
+}
+
+impl ApiTokens {
+    pub fn new(collection: Collection<ApiToken>) -> Self {
+        Self { collection }
+    }
+
+    pub async fn insert(&self, id: &str, name: &str, hash: &str, scopes: &[ApiTokenScope]) -> Result<ApiToken> {
+        let api_token = ApiToken {
+            id: id.into(),
+            name: name.into(),
+            hash: hash.into(),
+            scopes: scopes.to_vec(),
+            created_at: Utc::now().to_rfc3339(),
+            last_used_at: None,
+            revoked_at: None,
+        };
+
+        self.collection.insert_one(&api_token).await?;
+        Ok(api_token)
+    }
+
+    pub async fn find(&self) -> Result<Vec<ApiToken>> {
+        Ok(self.collection.find(doc! {}).sort(doc! { "createdAt": 1 }).await?.try_collect().await?)
+    }
+
+    // Revoked tokens are kept so they still show up in the list
+    pub async fn get_active_by_hash(&self, hash: &str) -> Result<Option<ApiToken>> {
+        Ok(self.collection.find_one(doc! { "hash": hash, "revokedAt": null }).await?)
+    }
+
+    pub async fn set_last_used(&self, id: &str) -> Result<()> {
+        self.collection.update_one(doc! { "_id": id }, doc! { "$set": { "lastUsedAt": Utc::now().to_rfc3339() } }).await?;
+        Ok(())
+    }
+
+    // Returns whether there was an active token with the ID
+    pub async fn revoke(&self, id: &str) -> Result<bool> {
+        let result = self
+            .collection
+            .update_one(doc! { "_id": id, "revokedAt": null }, doc! { "$set": { "revokedAt": Utc::now().to_rfc3339() } })
+            .await?;
+
+        Ok(result.modified_count > 0)
+    }
+}
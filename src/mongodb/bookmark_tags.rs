@@ -1,22 +1,45 @@
-use crate::mongodb::BookmarkTag;
+use crate::{
+    mongodb::{
+        AutocompleteCursor, BookmarkTag, BookmarkTagNameChange, BookmarkTagNameChangeSource, BookmarkTagTranslations, TagTranslationJob,
+        tag_translation_queue::TagTranslationQueue,
+    },
+    search::{edit_distance, normalize, search_keys},
+};
 use anyhow::Result;
+use chrono::Utc;
 use futures::TryStreamExt;
 use mongodb::{
-    Collection,
-    bson::{Regex, doc},
+    Collection, IndexModel,
+    bson::{Document, Regex, doc},
     options::FindOneAndUpdateOptions,
 };
 use regex_syntax::escape;
-use std::fmt::Display;
+use std::{cmp::Ordering, fmt::Display};
+
+// How many of the most used matching tags are ranked for autocompletion
+const AUTOCOMPLETE_CANDIDATES_LIMIT: i64 = 2000;
 
 #[derive(Debug)]
 pub struct BookmarkTags {
     collection: Collection<BookmarkTag>,
+    name_history: Collection<BookmarkTagNameChange>,
+    pub queue: TagTranslationQueue,
 }
 
 impl BookmarkTags {
-    pub fn new(collection: Collection<BookmarkTag>) -> Self {
-        Self { collection }
+    pub fn new(
+        collection: Collection<BookmarkTag>,
+        name_history: Collection<BookmarkTagNameChange>,
+        queue_collection: Collection<TagTranslationJob>,
+    ) -> Self {
+        let queue = TagTranslationQueue::new(queue_collection);
+        Self { collection, name_history, queue }
+    }
+
+    // Prefix matches of the search keys can use this index, which autocompletion relies on since they aren't capped
+    pub async fn create_indexes(&self) -> Result<()> {
+        self.collection.create_index(IndexModel::builder().keys(doc! { "searchKeys": 1 }).build()).await?;
+        Ok(())
     }
 
     pub async fn get<T: Display>(&self, id: T) -> Result<Option<BookmarkTag>> {
@@ -49,6 +72,99 @@ impl BookmarkTags {
         Ok(self.collection.find(filter).sort(doc! { "total": -1 }).limit(50).await?.try_collect().await?)
     }
 
+    pub async fn find_by_ids<T: Display>(&self, ids: &[T]) -> Result<Vec<BookmarkTag>> {
+        let ids = ids.iter().map(|id| id.to_string().to_lowercase()).collect::<Vec<String>>();
+        Ok(self.collection.find(doc! { "_id": { "$in": ids } }).sort(doc! { "total": -1 }).await?.try_collect().await?)
+    }
+
+    // Ranks tags by how well any of their search keys match the query (exact, prefix, substring or with typos) and by their total
+    // This returns the page after the cursor, and the cursor of the next page if there are more results
+    pub async fn autocomplete<T: Display>(
+        &self,
+        query: T,
+        cursor: Option<&AutocompleteCursor>,
+        limit: usize,
+    ) -> Result<(Vec<BookmarkTag>, Option<AutocompleteCursor>)> {
+        let query = normalize(&query.to_string());
+
+        let Some(first_char) = query.chars().next() else { return Ok((vec![], None)) };
+        let prefix = Regex { pattern: format!("^{}", escape(&query)), options: "".into() };
+
+        // Exact and prefix matches are always ranked, no matter how rarely they're used
+        let mut candidates = self.collection.find(doc! { "searchKeys": &prefix }).await?.try_collect::<Vec<BookmarkTag>>().await?;
+
+        // Tags with typos are only found if the first character is right, which keeps the candidates manageable
+        // Only these are capped, as the most used ones are the most likely to be meant
+        let filter = doc! {
+            "$or": [
+                { "searchKeys": Regex { pattern: escape(&query), options: "".into() } },
+                { "searchKeys": Regex { pattern: format!("^{}", escape(&first_char.to_string())), options: "".into() } },
+            ],
+            "searchKeys": { "$not": prefix },
+        };
+
+        candidates.extend(
+            self.collection
+                .find(filter)
+                .sort(doc! { "total": -1 })
+                .limit(AUTOCOMPLETE_CANDIDATES_LIMIT)
+                .await?
+                .try_collect::<Vec<BookmarkTag>>()
+                .await?,
+        );
+
+        let max_typos = match query.chars().count() {
+            0..=2 => 0,
+            3..=5 => 1,
+            _ => 2,
+        };
+
+        // The popularity doesn't depend on the other candidates, so the scores in the cursors stay comparable between requests
+        let mut ranked = candidates
+            .into_iter()
+            .filter_map(|candidate| {
+                let quality = candidate.search_keys.iter().filter_map(|key| match_quality(&query, key, max_typos)).reduce(f64::max)?;
+                let log_total = (candidate.total as f64).ln_1p();
+                let popularity = log_total / (log_total + 1.0);
+                let score = ((quality * 0.7 + popularity * 0.3) * 1e9).round() as u64;
+                let rank = AutocompleteCursor { score, total: candidate.total, id: candidate.id.clone() };
+                Some((rank, candidate))
+            })
+            .filter(|(rank, _)| cursor.is_none_or(|cursor| compare_ranks(rank, cursor) == Ordering::Greater))
+            .collect::<Vec<(AutocompleteCursor, BookmarkTag)>>();
+
+        ranked.sort_by(|a, b| compare_ranks(&a.0, &b.0));
+
+        let has_more = ranked.len() > limit;
+        ranked.truncate(limit);
+
+        let next = ranked.last().filter(|_| has_more).map(|(rank, _)| rank.clone());
+        let page = ranked.into_iter().map(|(_, bookmark_tag)| bookmark_tag).collect();
+
+        Ok((page, next))
+    }
+
+    pub async fn update_search_keys<T: Display>(&self, id: T) -> Result<()> {
+        let Some(bookmark_tag) = self.get(id).await? else { return Ok(()) };
+        let search_keys = search_keys(&bookmark_tag.id, bookmark_tag.name.as_deref(), &bookmark_tag.translations);
+        self.collection.update_one(doc! { "_id": bookmark_tag.id }, doc! { "$set": { "searchKeys": search_keys } }).await?;
+        Ok(())
+    }
+
+    // Indexes the tags from before search keys existed, returning how many were indexed
+    pub async fn index_missing_search_keys(&self) -> Result<u64> {
+        let mut cursor = self.collection.find(doc! { "searchKeys": { "$exists": false } }).await?;
+        let mut count = 0;
+
+        while let Some(bookmark_tag) = cursor.try_next().await? {
+            let search_keys = search_keys(&bookmark_tag.id, bookmark_tag.name.as_deref(), &bookmark_tag.translations);
+            self.collection.update_one(doc! { "_id": bookmark_tag.id }, doc! { "$set": { "searchKeys": search_keys } }).await?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
     pub async fn resolve_from_name_or_id<T: Display>(&self, name_or_id: T) -> Result<Vec<BookmarkTag>> {
         let name_or_id = name_or_id.to_string().to_string();
         let filter = doc! { "$or": [{ "_id": &name_or_id }, { "name": name_or_id }] };
@@ -58,7 +174,14 @@ impl BookmarkTags {
     pub async fn increment<T: Display>(&self, id: T) -> Result<()> {
         let id = id.to_string().to_lowercase();
         let options = FindOneAndUpdateOptions::builder().upsert(true).build();
-        self.collection.find_one_and_update(doc! { "_id": id }, doc! { "$inc": { "total": 1 } }).with_options(options).await?;
+        let previous =
+            self.collection.find_one_and_update(doc! { "_id": &id }, doc! { "$inc": { "total": 1 } }).with_options(options).await?;
+
+        // There's no previous tag if it was just inserted
+        if previous.is_none() {
+            self.update_search_keys(&id).await?;
+        }
+
         Ok(())
     }
 
@@ -68,16 +191,128 @@ impl BookmarkTags {
         Ok(())
     }
 
+    // This is used for automatic translations, so locked names are left alone
     pub async fn set_name<T: Display, U: Display>(&self, id: T, name: U) -> Result<()> {
         let id = id.to_string().to_lowercase();
+        let filter = doc! { "_id": &id, "locked": { "$ne": true } };
+        self.update_name(&id, filter, doc! { "manual": false }, name, BookmarkTagNameChangeSource::Automatic).await?;
+        Ok(())
+    }
+
+    // Returns false if the tag doesn't exist
+    pub async fn set_name_manually<T: Display, U: Display>(&self, id: T, name: U, locked: bool) -> Result<bool> {
+        let id = id.to_string().to_lowercase();
+        self.update_name(&id, doc! { "_id": &id }, doc! { "locked": locked, "manual": true }, name, BookmarkTagNameChangeSource::Manual)
+            .await
+    }
+
+    async fn update_name<T: Display>(
+        &self,
+        id: &str,
+        filter: Document,
+        mut set: Document,
+        name: T,
+        source: BookmarkTagNameChangeSource,
+    ) -> Result<bool> {
         let name = name.to_string().to_lowercase();
-        self.collection.update_one(doc! { "_id": id }, doc! { "$set": { "name": name } }).await?;
+        set.insert("name", &name);
+
+        // This returns the tag from before the update, so we know if the name actually changed
+        let Some(previous) = self.collection.find_one_and_update(filter, doc! { "$set": set }).await? else { return Ok(false) };
+
+        if previous.name.as_ref() != Some(&name) {
+            let change = BookmarkTagNameChange {
+                tag: id.to_string(),
+                old_name: previous.name,
+                new_name: Some(name),
+                source,
+                date: Utc::now().to_rfc3339(),
+            };
+
+            self.name_history.insert_one(change).await?;
+            self.update_search_keys(id).await?;
+        }
+
+        Ok(true)
+    }
+
+    // Returns false if the tag doesn't exist
+    pub async fn set_locked<T: Display>(&self, id: T, locked: bool) -> Result<bool> {
+        let id = id.to_string().to_lowercase();
+        Ok(self.collection.update_one(doc! { "_id": id }, doc! { "$set": { "locked": locked } }).await?.matched_count != 0)
+    }
+
+    pub async fn get_name_history<T: Display>(&self, id: T) -> Result<Vec<BookmarkTagNameChange>> {
+        let id = id.to_string().to_lowercase();
+        Ok(self.name_history.find(doc! { "tag": id }).sort(doc! { "date": -1 }).await?.try_collect().await?)
+    }
+
+    // Only the given translations are set, so translations from other lookups are kept
+    pub async fn set_translations<T: Display>(&self, id: T, translations: &BookmarkTagTranslations) -> Result<()> {
+        let id = id.to_string().to_lowercase();
+        let mut set = doc! {};
+
+        for (lang, translation) in [
+            ("en", &translations.en),
+            ("ko", &translations.ko),
+            ("zh", &translations.zh),
+            ("zh_tw", &translations.zh_tw),
+            ("romaji", &translations.romaji),
+        ] {
+            if let Some(translation) = translation {
+                set.insert(format!("translations.{lang}"), translation);
+            }
+        }
+
+        if !set.is_empty() {
+            self.collection.update_one(doc! { "_id": &id }, doc! { "$set": set }).await?;
+            self.update_search_keys(&id).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn set_translated<T: Display>(&self, id: T) -> Result<()> {
+        let id = id.to_string().to_lowercase();
+        self.collection.update_one(doc! { "_id": id }, doc! { "$set": { "translatedAt": Utc::now().to_rfc3339() } }).await?;
         Ok(())
     }
 
+    // Makes the tags get looked up on pixiv again the next time they're translated
+    pub async fn unset_translated<T: Display>(&self, ids: &[T]) -> Result<u64> {
+        let ids = ids.iter().map(|id| id.to_string().to_lowercase()).collect::<Vec<String>>();
+        Ok(self.collection.update_many(doc! { "_id": { "$in": ids } }, doc! { "$unset": { "translatedAt": "" } }).await?.modified_count)
+    }
+
     pub async fn delete<T: Display>(&self, id: T) -> Result<()> {
         let id = id.to_string().to_lowercase();
         self.collection.delete_one(doc! { "_id": id }).await?;
         Ok(())
     }
 }
+
+// Higher scores come first, then higher totals, and the ID breaks ties
+fn compare_ranks(a: &AutocompleteCursor, b: &AutocompleteCursor) -> Ordering {
+    b.score.cmp(&a.score).then_with(|| b.total.cmp(&a.total)).then_with(|| a.id.cmp(&b.id))
+}
+
+// Returns how well a search key matches the query from 0 to 1, or None if it doesn't match
+fn match_quality(query: &str, key: &str, max_typos: usize) -> Option<f64> {
+    if key == query {
+        return Some(1.0);
+    }
+
+    if key.starts_with(query) {
+        return Some(0.8);
+    }
+
+    if key.contains(query) {
+        return Some(0.6);
+    }
+
+    // Compare against the start of the key so typos are found while the query is still being typed
+    let key_start = key.chars().take(query.chars().count()).collect::<String>();
+    let distance = edit_distance(query, &key_start).min(edit_distance(query, key));
+
+    (distance <= max_typos).then_some(0.5 - 0.1 * distance as f64)
+}
@@ -1,22 +1,29 @@
 use crate::{
-    mongodb::{BookmarkTag, bookmark_tags::BookmarkTags},
-    pixiv::{PIXIV_BOOKMARKS_PER_PAGE, PixivBookmarkPageBodyWork},
-    routes::bookmarks::PaginationSort,
-    sync::sync_bookmark_tag_translations,
+    METRICS,
+    mongodb::{
+        BookmarkCursor, BookmarkFacet, BookmarkOrder, BookmarkTag, BookmarkTagNameChange, FacetBucket, TagCount, TagLanguage,
+        TagTranslationJob, bookmark_tags::BookmarkTags,
+    },
+    pixiv::{PIXIV_BOOKMARKS_PER_PAGE, PixivArtworkState, PixivBookmarkPageBodyWork},
+    routes::bookmarks::{PaginationSort, PaginationSortBy},
 };
 use anyhow::Result;
 use chrono::Utc;
 use futures::TryStreamExt;
 use mongodb::{
-    Collection,
-    bson::{Document, doc},
+    Collection, IndexModel,
+    bson::{Bson, Document, doc, from_document, to_bson},
+    error::{ErrorKind, InsertManyError},
     options::FindOptions,
 };
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Display,
 };
-use tokio::spawn;
+use tracing::error;
+
+// The number of top artists in the artists facet
+const FACET_ARTISTS_LIMIT: i64 = 20;
 
 #[derive(Debug)]
 pub struct Bookmarks {
@@ -25,11 +32,28 @@ pub struct Bookmarks {
 }
 
 impl Bookmarks {
-    pub fn new(collection: Collection<PixivBookmarkPageBodyWork>, tags_collection: Collection<BookmarkTag>) -> Self {
-        let tags = BookmarkTags::new(tags_collection);
+    pub fn new(
+        collection: Collection<PixivBookmarkPageBodyWork>,
+        tags_collection: Collection<BookmarkTag>,
+        tag_name_history_collection: Collection<BookmarkTagNameChange>,
+        tag_translation_queue_collection: Collection<TagTranslationJob>,
+    ) -> Self {
+        let tags = BookmarkTags::new(tags_collection, tag_name_history_collection, tag_translation_queue_collection);
         Self { collection, tags }
     }
 
+    // Every stored field the bookmarks can be sorted by, with the ID as the tiebreaker like in `find`
+    pub async fn create_indexes(&self) -> Result<()> {
+        let indexes = ["_syncDate", "createDate", "updateDate", "pageCount"]
+            .into_iter()
+            .map(|field| IndexModel::builder().keys(doc! { field: 1, "_id": 1 }).build());
+
+        self.collection.create_indexes(indexes).await?;
+        self.tags.create_indexes().await?;
+
+        Ok(())
+    }
+
     pub async fn count<T: Into<Option<Document>>>(&self, filter: T) -> Result<u64> {
         Ok(self.collection.count_documents(filter.into().unwrap_or_default()).await?)
     }
@@ -38,51 +62,204 @@ impl Bookmarks {
         Ok(self.collection.find_one(doc! { "_id": id.to_string() }).await?)
     }
 
+    // Unlike `find`, this returns the stored bookmarks as is without any limit or tag translation
+    pub async fn find_raw(&self, filter: Document) -> Result<Vec<PixivBookmarkPageBodyWork>> {
+        Ok(self.collection.find(filter).await?.try_collect().await?)
+    }
+
+    // Returns the bookmarks with their sort keys, which the cursors are made from
     pub async fn find<T: Into<Option<Document>>>(
         &self,
         filter: T,
         offset: u64,
-        mut limit: i64,
-        sort: PaginationSort,
-    ) -> Result<Vec<PixivBookmarkPageBodyWork>> {
-        if limit > PIXIV_BOOKMARKS_PER_PAGE {
-            limit = PIXIV_BOOKMARKS_PER_PAGE;
-        }
+        limit: i64,
+        order: BookmarkOrder,
+        cursor: Option<&BookmarkCursor>,
+        lang: Option<TagLanguage>,
+    ) -> Result<Vec<(PixivBookmarkPageBodyWork, Bson)>> {
+        // MongoDB rejects a $limit that isn't positive
+        let limit = limit.clamp(1, PIXIV_BOOKMARKS_PER_PAGE);
+        let mut offset = offset;
+        let backwards = cursor.is_some_and(|cursor| cursor.backwards);
 
-        let sort = doc! {
-            "_syncDate": match sort {
-                PaginationSort::Ascending => 1,
-                PaginationSort::Descending => -1,
-            },
+        // Going backwards is the same as going forwards in the opposite order, and the results are reversed afterwards
+        let direction = match (order.sort, backwards) {
+            (PaginationSort::Ascending, false) | (PaginationSort::Descending, true) => 1,
+            (PaginationSort::Descending, false) | (PaginationSort::Ascending, true) => -1,
         };
 
-        let find_options = FindOptions::builder().limit(limit).sort(sort).skip(offset).build();
-        let mut bookmarks = self
-            .collection
-            .find(filter.into().unwrap_or_default())
-            .with_options(find_options)
-            .await?
-            .try_collect::<Vec<PixivBookmarkPageBodyWork>>()
-            .await?;
+        // Stored fields are sorted on directly so the indexes are used, and only the other orders need a computed key
+        let stored_field = sort_field(order.sort_by);
+        let key = stored_field.unwrap_or("_sortKey");
+        let mut pipeline = vec![doc! { "$match": filter.into().unwrap_or_default() }];
 
-        let unique_tags = HashSet::<String>::from_iter(bookmarks.iter().flat_map(|bookmark| bookmark.tags.clone())).into_iter().collect();
+        if stored_field.is_none() {
+            pipeline.push(doc! { "$addFields": { "_sortKey": sort_key(order) } });
+        }
+
+        // Unlike an offset, this continues right after the last seen bookmark even if new bookmarks were inserted in the meantime
+        // The ID breaks ties between bookmarks with the same sort key
+        if let Some(cursor) = cursor {
+            let operator = if direction == 1 { "$gt" } else { "$lt" };
+
+            pipeline.push(doc! {
+                "$match": {
+                    "$or": [
+                        { key: { operator: &cursor.value } },
+                        { key: &cursor.value, "_id": { operator: &cursor.id } },
+                    ],
+                },
+            });
+
+            offset = 0;
+        }
+
+        pipeline.extend([
+            doc! { "$sort": { key: direction, "_id": direction } },
+            doc! { "$skip": offset as i64 },
+            doc! { "$limit": limit },
+        ]);
+
+        let documents = self.collection.aggregate(pipeline).await?.try_collect::<Vec<Document>>().await?;
+        let mut bookmarks = vec![];
+
+        for mut document in documents {
+            let sort_key = match stored_field {
+                Some(field) => document.get(field).cloned(),
+                None => document.remove("_sortKey"),
+            };
+
+            bookmarks.push((from_document::<PixivBookmarkPageBodyWork>(document)?, sort_key.unwrap_or(Bson::Null)));
+        }
+
+        if backwards {
+            bookmarks.reverse();
+        }
+
+        self.translate_tags(bookmarks.iter_mut().map(|(bookmark, _)| bookmark).collect(), lang).await;
+
+        Ok(bookmarks)
+    }
+
+    // Like `get`, but with the tags translated the same way as `find`
+    pub async fn get_translated<T: Display>(&self, id: T, lang: Option<TagLanguage>) -> Result<Option<PixivBookmarkPageBodyWork>> {
+        let mut bookmark = self.get(id).await?;
+        self.translate_tags(bookmark.iter_mut().collect(), lang).await;
+
+        Ok(bookmark)
+    }
+
+    // Returns the stored bookmarks out of the given IDs, in the same order and with the tags translated
+    pub async fn lookup(&self, ids: &[String], lang: Option<TagLanguage>) -> Result<Vec<PixivBookmarkPageBodyWork>> {
+        let mut bookmarks = self.find_raw(doc! { "_id": { "$in": ids } }).await?;
+        bookmarks.sort_by_key(|bookmark| ids.iter().position(|id| id == &bookmark.id));
+        self.translate_tags(bookmarks.iter_mut().collect(), lang).await;
+
+        Ok(bookmarks)
+    }
+
+    // Replaces the tags of the bookmarks with their display names and queues the untranslated ones
+    async fn translate_tags(&self, bookmarks: Vec<&mut PixivBookmarkPageBodyWork>, lang: Option<TagLanguage>) {
+        let unique_tags = HashSet::<String>::from_iter(bookmarks.iter().flat_map(|bookmark| bookmark.tags.clone()));
         let mut translated_tags = HashMap::new();
+        let mut untranslated_tags = vec![];
 
         for tag in &unique_tags {
             let bookmark_tags = self.tags.resolve_from_name_or_id(&tag).await.unwrap_or_default();
-            let iter =
-                bookmark_tags.into_iter().map(|bookmark_tag| (bookmark_tag.id.clone(), bookmark_tag.name.unwrap_or(bookmark_tag.id)));
-            translated_tags.extend(iter);
+
+            for bookmark_tag in bookmark_tags {
+                if bookmark_tag.translated_at.is_none() && &bookmark_tag.id == tag {
+                    untranslated_tags.push(bookmark_tag.id.clone());
+                }
+
+                translated_tags.insert(bookmark_tag.id.clone(), bookmark_tag.display_name(lang));
+            }
         }
 
-        for bookmark in &mut bookmarks {
+        for bookmark in bookmarks {
             let iter = bookmark.tags.iter().map(|tag| translated_tags.get(tag).unwrap_or(tag).clone());
             bookmark.tags = HashSet::<String>::from_iter(iter).into_iter().collect();
         }
 
-        spawn(sync_bookmark_tag_translations(unique_tags));
+        // The tags are translated in the background by the tag translation queue worker
+        if let Err(error) = self.tags.queue.enqueue(&untranslated_tags).await {
+            error!("An error occurred while trying to queue tags for translation: {error:?}");
+        }
+    }
 
-        Ok(bookmarks)
+    // Counts the tags of the bookmarks matching the filter, except the excluded ones (which are usually the searched tags)
+    pub async fn count_co_occurring_tags<T: Into<Option<Document>>>(
+        &self,
+        filter: T,
+        exclude: &[String],
+        limit: i64,
+    ) -> Result<Vec<TagCount>> {
+        let pipeline = [
+            doc! { "$match": filter.into().unwrap_or_default() },
+            doc! { "$unwind": "$tags" },
+            doc! { "$match": { "tags": { "$nin": exclude } } },
+            doc! { "$group": { "_id": "$tags", "count": { "$sum": 1 } } },
+            doc! { "$sort": { "count": -1, "_id": 1 } },
+            doc! { "$limit": limit },
+        ];
+
+        Ok(self.collection.aggregate(pipeline).with_type::<TagCount>().await?.try_collect().await?)
+    }
+
+    // Computes every requested facet of the bookmarks matching the filter in a single aggregation
+    pub async fn facets<T: Into<Option<Document>>>(
+        &self,
+        filter: T,
+        facets: &[BookmarkFacet],
+    ) -> Result<BTreeMap<String, Vec<FacetBucket>>> {
+        if facets.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        let facet_pipelines =
+            facets.iter().map(|facet| (facet.name().to_string(), Bson::from(facet_pipeline(*facet)))).collect::<Document>();
+        let pipeline = [doc! { "$match": filter.into().unwrap_or_default() }, doc! { "$facet": facet_pipelines }];
+
+        let Some(result) = self.collection.aggregate(pipeline).await?.try_next().await? else { return Ok(BTreeMap::new()) };
+        Ok(from_document(result)?)
+    }
+
+    // Counts the bookmarks matching the filter and each of the sub-filters, in a single aggregation
+    pub async fn count_each<T: Into<Option<Document>>>(&self, filter: T, sub_filters: &[Document]) -> Result<Vec<u64>> {
+        if sub_filters.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Facet names can't contain dots or start with a dollar sign, so the sub-filters are keyed by index
+        let facet_pipelines = sub_filters
+            .iter()
+            .enumerate()
+            .map(|(index, sub_filter)| {
+                (index.to_string(), Bson::from(vec![doc! { "$match": sub_filter.clone() }, doc! { "$count": "count" }]))
+            })
+            .collect::<Document>();
+        let pipeline = [doc! { "$match": filter.into().unwrap_or_default() }, doc! { "$facet": facet_pipelines }];
+
+        let result = self.collection.aggregate(pipeline).await?.try_next().await?.unwrap_or_default();
+        let counts = (0..sub_filters.len())
+            .map(|index| {
+                // $count produces no document at all when nothing matched
+                let count = result
+                    .get_array(index.to_string())
+                    .ok()
+                    .and_then(|counts| counts.first())
+                    .and_then(Bson::as_document)
+                    .and_then(|count| count.get("count"));
+
+                match count {
+                    Some(Bson::Int32(count)) => *count as u64,
+                    Some(Bson::Int64(count)) => *count as u64,
+                    _ => 0,
+                }
+            })
+            .collect();
+
+        Ok(counts)
     }
 
     pub async fn insert_many(&self, bookmarks: Vec<PixivBookmarkPageBodyWork>) -> Result<()> {
@@ -100,13 +277,103 @@ impl Bookmarks {
             })
             .collect::<Vec<PixivBookmarkPageBodyWork>>();
 
-        for bookmark in &bookmarks {
+        // Unordered so one bookmark that already exists (like when it's added through the API during a sync) doesn't stop the rest
+        let result = self.collection.insert_many(&bookmarks).ordered(false).await;
+
+        // Only the bookmarks that were actually inserted count towards the tag totals
+        let failed_indexes = match &result {
+            Ok(_) => vec![],
+            Err(error) => match &*error.kind {
+                ErrorKind::InsertMany(InsertManyError { write_errors, .. }) => {
+                    write_errors.iter().flatten().map(|write_error| write_error.index).collect()
+                },
+                _ => (0..bookmarks.len()).collect(),
+            },
+        };
+
+        let inserted_bookmarks = bookmarks
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !failed_indexes.contains(index))
+            .map(|(_, bookmark)| bookmark)
+            .collect::<Vec<&PixivBookmarkPageBodyWork>>();
+
+        for bookmark in &inserted_bookmarks {
             for tag in &bookmark.tags {
                 self.tags.increment(tag).await?;
             }
         }
 
-        self.collection.insert_many(bookmarks).await?;
+        METRICS.bookmarks_inserted.inc_by(inserted_bookmarks.len() as u64);
+        result?;
+
+        Ok(())
+    }
+
+    pub async fn set_user_tags<T: Display>(&self, ids: &[T], user_tags: &[String]) -> Result<()> {
+        let ids = ids.iter().map(|id| id.to_string()).collect::<Vec<String>>();
+        self.collection.update_many(doc! { "_id": { "$in": ids } }, doc! { "$set": { "userTags": user_tags } }).await?;
+        Ok(())
+    }
+
+    pub async fn clear_user_tags_except<T: Display>(&self, ids: &[T]) -> Result<()> {
+        let ids = ids.iter().map(|id| id.to_string()).collect::<Vec<String>>();
+        let filter = doc! { "_id": { "$nin": ids }, "userTags": { "$exists": true, "$ne": [] } };
+        self.collection.update_many(filter, doc! { "$set": { "userTags": [] } }).await?;
+        Ok(())
+    }
+
+    pub async fn add_user_tags<T: Display>(&self, ids: &[T], user_tags: &[String]) -> Result<()> {
+        let ids = ids.iter().map(|id| id.to_string()).collect::<Vec<String>>();
+        let update = doc! { "$addToSet": { "userTags": { "$each": user_tags } } };
+        self.collection.update_many(doc! { "_id": { "$in": ids } }, update).await?;
+        Ok(())
+    }
+
+    pub async fn remove_user_tags<T: Display>(&self, ids: &[T], user_tags: &[String]) -> Result<()> {
+        let ids = ids.iter().map(|id| id.to_string()).collect::<Vec<String>>();
+        self.collection.update_many(doc! { "_id": { "$in": ids } }, doc! { "$pullAll": { "userTags": user_tags } }).await?;
+        Ok(())
+    }
+
+    // Returns the IDs of the bookmarks that weren't validated since the given date, starting from the ones that never were
+    // Bookmarks that failed to validate are skipped until the retry date, so a persistent error doesn't retry them endlessly
+    pub async fn find_unvalidated_ids(&self, since: &str, retry_since: &str, limit: i64) -> Result<Vec<String>> {
+        let filter = doc! {
+            "$or": [{ "lastValidatedAt": { "$exists": false } }, { "lastValidatedAt": { "$lt": since } }],
+            "validationAttemptedAt": { "$not": { "$gte": retry_since } },
+        };
+        let find_options = FindOptions::builder().sort(doc! { "lastValidatedAt": 1 }).limit(limit).projection(doc! { "_id": 1 }).build();
+        let documents = self
+            .collection
+            .clone_with_type::<Document>()
+            .find(filter)
+            .with_options(find_options)
+            .await?
+            .try_collect::<Vec<Document>>()
+            .await?;
+
+        Ok(documents.into_iter().filter_map(|document| document.get_str("_id").ok().map(|id| id.to_string())).collect())
+    }
+
+    // Returns whether the state is different from the stored one
+    pub async fn set_validation_state<T: Display>(&self, id: T, state: PixivArtworkState) -> Result<bool> {
+        let update = doc! {
+            "$set": { "validationState": to_bson(&state)?, "lastValidatedAt": Utc::now().to_rfc3339() },
+            "$unset": { "validationAttemptedAt": "" },
+        };
+
+        // This returns the bookmark from before the update
+        let previous = self.collection.find_one_and_update(doc! { "_id": id.to_string() }, update).await?;
+
+        Ok(previous.is_some_and(|previous| previous.validation_state != Some(state)))
+    }
+
+    // Records a failed validation, which keeps the previous state
+    pub async fn set_validation_attempt<T: Display>(&self, id: T) -> Result<()> {
+        let update = doc! { "$set": { "validationAttemptedAt": Utc::now().to_rfc3339() } };
+        self.collection.update_one(doc! { "_id": id.to_string() }, update).await?;
+
         Ok(())
     }
 
@@ -125,7 +392,94 @@ impl Bookmarks {
             }
         }
 
-        self.collection.delete_one(doc! { "_id": id }).await?;
+        let result = self.collection.delete_one(doc! { "_id": id }).await?;
+        METRICS.bookmarks_deleted.inc_by(result.deleted_count);
+
         Ok(())
     }
 }
+
+fn facet_pipeline(facet: BookmarkFacet) -> Vec<Document> {
+    // Groups by a label from the first matching upper bound, ordered by the smallest value in each group
+    let bucket = |value: Bson, bounds: &[(f64, &str)], default: &str| {
+        let branches =
+            bounds.iter().map(|(bound, label)| doc! { "case": { "$lt": [&value, bound] }, "then": label }).collect::<Vec<Document>>();
+
+        vec![
+            doc! { "$group": { "_id": { "$switch": { "branches": branches, "default": default } }, "count": { "$sum": 1 }, "order": { "$min": &value } } },
+            doc! { "$sort": { "order": 1 } },
+        ]
+    };
+
+    let group_by =
+        |field: &str| vec![doc! { "$group": { "_id": format!("${field}"), "count": { "$sum": 1 } } }, doc! { "$sort": { "_id": 1 } }];
+
+    match facet {
+        BookmarkFacet::Artists => vec![
+            doc! { "$group": { "_id": "$userId", "label": { "$first": "$userName" }, "count": { "$sum": 1 } } },
+            doc! { "$sort": { "count": -1, "_id": 1 } },
+            doc! { "$limit": FACET_ARTISTS_LIMIT },
+        ],
+        BookmarkFacet::XRestrict => group_by("xRestrict"),
+        BookmarkFacet::AiType => group_by("aiType"),
+        BookmarkFacet::IllustType => group_by("illustType"),
+        BookmarkFacet::PageCount => {
+            bucket("$pageCount".into(), &[(2.0, "1"), (5.0, "2-4"), (10.0, "5-9"), (20.0, "10-19"), (50.0, "20-49")], "50+")
+        },
+        BookmarkFacet::AspectRatio => bucket(
+            doc! { "$divide": ["$width", { "$max": ["$height", 1] }] }.into(),
+            &[(0.6, "tall"), (0.95, "portrait"), (1.05, "square"), (1.8, "landscape")],
+            "wide",
+        ),
+        // The sync date is an RFC 3339 string, so the year and month are the first 7 characters
+        BookmarkFacet::BookmarkDate => vec![
+            doc! { "$group": { "_id": { "$substrBytes": ["$_syncDate", 0, 7] }, "count": { "$sum": 1 } } },
+            doc! { "$sort": { "_id": 1 } },
+        ],
+    }
+}
+
+// The modulus of the random order, which is prime so every multiplier shuffles the IDs differently
+const RANDOM_ORDER_MODULUS: i64 = 2_147_483_647;
+
+// Returns the stored field bookmarks are sorted by, if the order doesn't need a computed key
+fn sort_field(sort_by: PaginationSortBy) -> Option<&'static str> {
+    match sort_by {
+        PaginationSortBy::SyncDate => Some("_syncDate"),
+        PaginationSortBy::CreateDate => Some("createDate"),
+        PaginationSortBy::UpdateDate => Some("updateDate"),
+        PaginationSortBy::PageCount => Some("pageCount"),
+        _ => None,
+    }
+}
+
+// Returns the expression of the value bookmarks are sorted by, which `find` only needs for the orders without a stored field
+fn sort_key(order: BookmarkOrder) -> Bson {
+    match order.sort_by {
+        PaginationSortBy::SyncDate => "$_syncDate".into(),
+        PaginationSortBy::CreateDate => "$createDate".into(),
+        PaginationSortBy::UpdateDate => "$updateDate".into(),
+        PaginationSortBy::Title => doc! { "$toLower": "$title" }.into(),
+        PaginationSortBy::Artist => doc! { "$toLower": "$userName" }.into(),
+        PaginationSortBy::PageCount => "$pageCount".into(),
+        PaginationSortBy::Resolution => doc! { "$multiply": ["$width", "$height"] }.into(),
+        PaginationSortBy::AspectRatio => doc! {
+            "$cond": [{ "$eq": ["$height", 0] }, 0.0, { "$divide": ["$width", "$height"] }],
+        }
+        .into(),
+        // A multiplicative hash of the ID, which is deterministic for the seed so pagination stays stable
+        // The product of an ID and the multiplier always fits in a long, so it never turns into an imprecise double
+        // The multiplier is kept between 2 and the modulus minus 2, as 1 and -1 would just sort by the ID
+        PaginationSortBy::Random => {
+            let multiplier = (order.seed as i64 * 48_271) % (RANDOM_ORDER_MODULUS - 3) + 2;
+
+            doc! {
+                "$mod": [
+                    { "$multiply": [{ "$convert": { "input": "$_id", "to": "long", "onError": 0_i64, "onNull": 0_i64 } }, multiplier] },
+                    RANDOM_ORDER_MODULUS,
+                ],
+            }
+            .into()
+        },
+    }
+}
@@ -1,26 +1,73 @@
+mod api_tokens;
 mod bookmark_tags;
 mod bookmarks;
+mod tag_graph;
+mod tag_mappings;
+mod tag_translation_queue;
 
-use crate::CONFIG;
+use crate::{
+    CONFIG,
+    metrics::track_mongodb_command,
+    pixiv::{PixivBookmarkPageBodyWork, PixivTagsBodyBreadcrumbsSuccessorTranslation, PixivTagsBodyTagTranslation},
+    routes::bookmarks::{PaginationSort, PaginationSortBy},
+};
 use anyhow::Result;
+use api_tokens::ApiTokens;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
 use bookmarks::Bookmarks;
-use mongodb::Client;
+use clap::ValueEnum;
+use mongodb::{
+    Client, Database,
+    bson::{Bson, doc},
+    event::EventHandler,
+    options::ClientOptions,
+};
 use serde::{Deserialize, Serialize};
+use tag_graph::TagGraph;
+use tag_mappings::TagMappings;
+use utoipa::ToSchema;
 
 #[derive(Debug)]
 pub struct MongoDB {
     pub bookmarks: Bookmarks,
+    pub tag_mappings: TagMappings,
+    pub tag_graph: TagGraph,
+    pub api_tokens: ApiTokens,
+    database: Database,
 }
 
 impl MongoDB {
     pub async fn new() -> Result<Self> {
-        let database = Client::with_uri_str(&CONFIG.mongodb_uri.to_string()).await?.database("flazxiv");
-        let bookmarks = Bookmarks::new(database.collection("bookmarks"), database.collection("bookmark-tags"));
-        Ok(Self { bookmarks })
+        let mut options = ClientOptions::parse(CONFIG.load().mongodb_uri.to_string()).await?;
+        options.command_event_handler = Some(EventHandler::callback(track_mongodb_command));
+
+        let database = Client::with_options(options)?.database("flazxiv");
+        let bookmarks = Bookmarks::new(
+            database.collection("bookmarks"),
+            database.collection("bookmark-tags"),
+            database.collection("bookmark-tag-name-history"),
+            database.collection("tag-translation-queue"),
+        );
+        let tag_mappings = TagMappings::new(database.collection("tag-mappings"));
+        let tag_graph = TagGraph::new(database.collection("tag-graph"));
+        let api_tokens = ApiTokens::new(database.collection("api-tokens"));
+        Ok(Self { bookmarks, tag_mappings, tag_graph, api_tokens, database })
+    }
+
+    // Creating indexes that already exist does nothing, so this is done on every startup
+    pub async fn create_indexes(&self) -> Result<()> {
+        self.bookmarks.create_indexes().await?;
+        Ok(())
+    }
+
+    pub async fn ping(&self) -> Result<()> {
+        self.database.run_command(doc! { "ping": 1 }).await?;
+        Ok(())
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Default, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct BookmarkTag {
     #[serde(rename(serialize = "_id"), alias = "_id")]
     pub id: String,
@@ -29,4 +76,357 @@ pub struct BookmarkTag {
 
     #[serde(default)]
     pub name: Option<String>,
+
+    #[serde(default)]
+    pub translations: BookmarkTagTranslations,
+
+    // Locked names are never overwritten by automatic translations
+    #[serde(default)]
+    pub locked: bool,
+
+    // Whether the name was set by an admin instead of an automatic translation
+    #[serde(default)]
+    pub manual: bool,
+
+    // Normalized forms of the tag, name and translations that autocompletion matches against
+    #[serde(default, skip_serializing)]
+    pub search_keys: Vec<String>,
+
+    // This is set once the tag has been looked up on pixiv, even if there were no translations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translated_at: Option<String>,
+
+    // The pixiv tags that are combined into this entry if it comes from a tag mapping
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+}
+
+// How bookmarks are ordered, which is also kept in the cursors so a cursor always continues in the same order
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct BookmarkOrder {
+    #[serde(rename = "k")]
+    pub sort_by: PaginationSortBy,
+
+    #[serde(rename = "s")]
+    pub sort: PaginationSort,
+
+    // Only used by the random order, where every seed shuffles the bookmarks differently
+    #[serde(rename = "r", default)]
+    pub seed: u32,
+}
+
+impl Default for BookmarkOrder {
+    fn default() -> Self {
+        Self { sort_by: PaginationSortBy::SyncDate, sort: PaginationSort::Descending, seed: 0 }
+    }
+}
+
+// Points at a bookmark to continue paginating from, which is given to clients as an opaque token
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BookmarkCursor {
+    #[serde(rename = "o")]
+    pub order: BookmarkOrder,
+
+    // The sort key of the bookmark, as computed by the database
+    #[serde(rename = "v")]
+    pub value: Bson,
+
+    #[serde(rename = "i")]
+    pub id: String,
+
+    // Whether this goes to the previous page instead of the next one
+    #[serde(rename = "b", default)]
+    pub backwards: bool,
+}
+
+impl BookmarkCursor {
+    pub fn new(order: BookmarkOrder, bookmark: &PixivBookmarkPageBodyWork, value: &Bson, backwards: bool) -> Self {
+        Self { order, value: value.clone(), id: bookmark.id.clone(), backwards }
+    }
+
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(token: &str) -> Option<Self> {
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(token).ok()?).ok()
+    }
+}
+
+// Points at the last autocompleted tag of a page by its rank, so the next page continues strictly after it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AutocompleteCursor {
+    // In billionths, since floats might not survive being encoded exactly
+    #[serde(rename = "s")]
+    pub score: u64,
+
+    #[serde(rename = "t")]
+    pub total: u64,
+
+    #[serde(rename = "i")]
+    pub id: String,
+}
+
+impl AutocompleteCursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(token: &str) -> Option<Self> {
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(token).ok()?).ok()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BookmarkFacet {
+    Artists,
+    XRestrict,
+    AiType,
+    IllustType,
+    PageCount,
+    AspectRatio,
+    BookmarkDate,
+}
+
+impl BookmarkFacet {
+    pub const ALL: [Self; 7] =
+        [Self::Artists, Self::XRestrict, Self::AiType, Self::IllustType, Self::PageCount, Self::AspectRatio, Self::BookmarkDate];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Artists => "artists",
+            Self::XRestrict => "x_restrict",
+            Self::AiType => "ai_type",
+            Self::IllustType => "illust_type",
+            Self::PageCount => "page_count",
+            Self::AspectRatio => "aspect_ratio",
+            Self::BookmarkDate => "bookmark_date",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|facet| facet.name() == name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct FacetBucket {
+    #[serde(rename(serialize = "value"), alias = "_id")]
+    #[schema(value_type = Value)]
+    pub value: Bson,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagCount {
+    #[serde(rename(serialize = "_id"), alias = "_id")]
+    pub id: String,
+
+    pub count: u64,
+}
+
+impl BookmarkTag {
+    // Picks the translation in the given language, falling back to romaji and then the tag itself
+    // Without a language, this is the name (which is usually English) as before
+    pub fn display_name(&self, lang: Option<TagLanguage>) -> String {
+        // Manual and locked names are corrections, so they win over the translations
+        if (self.manual || self.locked)
+            && let Some(name) = &self.name
+        {
+            return name.clone();
+        }
+
+        let Some(lang) = lang else { return self.name.clone().unwrap_or_else(|| self.id.clone()) };
+
+        match self.translations.get(lang).or(self.translations.romaji.as_ref()) {
+            Some(translation) => translation.split_whitespace().collect::<Vec<&str>>().join("_").to_lowercase(),
+            None => self.id.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug, ToSchema)]
+pub struct BookmarkTagTranslations {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub en: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ko: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zh: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zh_tw: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub romaji: Option<String>,
+}
+
+impl BookmarkTagTranslations {
+    pub fn get(&self, lang: TagLanguage) -> Option<&String> {
+        match lang {
+            TagLanguage::En => self.en.as_ref(),
+            TagLanguage::Ko => self.ko.as_ref(),
+            TagLanguage::Zh => self.zh.as_ref(),
+            TagLanguage::ZhTw => self.zh_tw.as_ref(),
+            TagLanguage::Romaji => self.romaji.as_ref(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        [&self.en, &self.ko, &self.zh, &self.zh_tw, &self.romaji].iter().all(|translation| translation.is_none())
+    }
+}
+
+impl From<&PixivTagsBodyBreadcrumbsSuccessorTranslation> for BookmarkTagTranslations {
+    fn from(translation: &PixivTagsBodyBreadcrumbsSuccessorTranslation) -> Self {
+        Self {
+            en: Some(translation.en.clone()),
+            ko: translation.ko.clone(),
+            zh: translation.zh.clone(),
+            zh_tw: translation.zh_tw.clone(),
+            romaji: translation.romaji.clone(),
+        }
+    }
+}
+
+impl From<&PixivTagsBodyTagTranslation> for BookmarkTagTranslations {
+    fn from(translation: &PixivTagsBodyTagTranslation) -> Self {
+        Self {
+            en: translation.en.clone(),
+            ko: translation.ko.clone(),
+            zh: translation.zh.clone(),
+            zh_tw: translation.zh_tw.clone(),
+            romaji: translation.romaji.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BookmarkTagNameChange {
+    pub tag: String,
+    pub old_name: Option<String>,
+    pub new_name: Option<String>,
+    pub source: BookmarkTagNameChangeSource,
+    pub date: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BookmarkTagNameChangeSource {
+    Automatic,
+    Manual,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TagTranslationJob {
+    #[serde(rename(serialize = "_id"), alias = "_id")]
+    pub id: String,
+
+    pub status: TagTranslationJobStatus,
+    pub attempts: u32,
+    pub enqueued_at: String,
+    pub next_attempt_at: String,
+
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TagTranslationJobStatus {
+    Pending,
+    Failed,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TagLanguage {
+    En,
+    Ko,
+    Zh,
+    ZhTw,
+    Romaji,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TagMapping {
+    #[serde(rename(serialize = "_id"), alias = "_id")]
+    pub name: String,
+
+    pub pixiv_tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagGraphNode {
+    #[serde(rename(serialize = "_id"), alias = "_id")]
+    pub id: String,
+
+    #[serde(default)]
+    pub parents: Vec<String>,
+
+    #[serde(default)]
+    pub children: Vec<String>,
+
+    // Tags that mean the same thing, like the same name in another language
+    #[serde(default)]
+    pub synonyms: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiToken {
+    #[serde(rename(serialize = "_id"), alias = "_id")]
+    pub id: String,
+
+    pub name: String,
+
+    // The SHA-256 hash of the token, since the token itself is only shown once when it's created
+    pub hash: String,
+
+    pub scopes: Vec<ApiTokenScope>,
+    pub created_at: String,
+
+    #[serde(default)]
+    pub last_used_at: Option<String>,
+
+    #[serde(default)]
+    pub revoked_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiTokenScope {
+    Read,
+
+    // Bookmarks that were made private on pixiv are hidden without this
+    #[serde(rename = "read:private")]
+    #[value(name = "read:private")]
+    ReadPrivate,
+
+    Validate,
+    Write,
+
+    // This allows everything else too
+    Admin,
+}
+
+impl ApiTokenScope {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::ReadPrivate => "read:private",
+            Self::Validate => "validate",
+            Self::Write => "write",
+            Self::Admin => "admin",
+        }
+    }
 }
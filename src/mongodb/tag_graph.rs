@@ -0,0 +1,100 @@
+use crate::mongodb::TagGraphNode;
+use anyhow::Result;
+use futures::TryStreamExt;
+use mongodb::{Collection, bson::doc};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+// How many levels of child tags are included when expanding a tag
+const MAX_EXPANSION_DEPTH: usize = 3;
+
+// Keeps the `$in` lists of searches reasonably small for huge tag trees
+const MAX_EXPANDED_TAGS: usize = 200;
+
+#[derive(Debug)]
+pub struct TagGraph {
+    collection: Collection<TagGraphNode>,
+}
+
+impl TagGraph {
+    pub fn new(collection: Collection<TagGraphNode>) -> Self {
+        Self { collection }
+    }
+
+    pub async fn get<T: Display>(&self, id: T) -> Result<Option<TagGraphNode>> {
+        let id = id.to_string().to_lowercase();
+        Ok(self.collection.find_one(doc! { "_id": id }).await?)
+    }
+
+    pub async fn add_child<T: Display, U: Display>(&self, parent: T, child: U) -> Result<()> {
+        let parent = parent.to_string().to_lowercase();
+        let child = child.to_string().to_lowercase();
+
+        if parent == child {
+            return Ok(());
+        }
+
+        self.collection.update_one(doc! { "_id": &parent }, doc! { "$addToSet": { "children": &child } }).upsert(true).await?;
+        self.collection.update_one(doc! { "_id": child }, doc! { "$addToSet": { "parents": parent } }).upsert(true).await?;
+
+        Ok(())
+    }
+
+    pub async fn add_synonym<T: Display, U: Display>(&self, a: T, b: U) -> Result<()> {
+        let a = a.to_string().to_lowercase();
+        let b = b.to_string().to_lowercase();
+
+        if a == b {
+            return Ok(());
+        }
+
+        self.collection.update_one(doc! { "_id": &a }, doc! { "$addToSet": { "synonyms": &b } }).upsert(true).await?;
+        self.collection.update_one(doc! { "_id": b }, doc! { "$addToSet": { "synonyms": a } }).upsert(true).await?;
+
+        Ok(())
+    }
+
+    // Returns the given tags along with their synonyms and child tags (and their synonyms), so parent tags match works with any child tag
+    pub async fn expand(&self, tags: &[String]) -> Result<Vec<String>> {
+        self.expand_to_depth(tags, MAX_EXPANSION_DEPTH).await
+    }
+
+    // Returns the given tags along with their synonyms, but none of their child tags
+    pub async fn with_synonyms(&self, tags: &[String]) -> Result<Vec<String>> {
+        self.expand_to_depth(tags, 0).await
+    }
+
+    // Fetches the nodes of a whole level at once, where synonyms stay on the same depth and children go one deeper
+    async fn expand_to_depth(&self, tags: &[String], max_depth: usize) -> Result<Vec<String>> {
+        let mut expanded = tags.iter().map(|tag| tag.to_lowercase()).collect::<Vec<String>>();
+        let mut seen = HashSet::<String>::from_iter(expanded.iter().cloned());
+        let mut level = HashMap::<String, usize>::from_iter(seen.iter().map(|tag| (tag.clone(), 0)));
+
+        while !level.is_empty() && expanded.len() < MAX_EXPANDED_TAGS {
+            let ids = level.keys().collect::<Vec<&String>>();
+            let nodes = self.collection.find(doc! { "_id": { "$in": ids } }).await?.try_collect::<Vec<TagGraphNode>>().await?;
+            let mut next_level = HashMap::new();
+
+            for node in nodes {
+                let Some(&depth) = level.get(&node.id) else { continue };
+                let synonyms = node.synonyms.into_iter().map(|synonym| (synonym, depth));
+                let children = node.children.into_iter().filter(|_| depth < max_depth).map(|child| (child, depth + 1));
+
+                for (related_tag, related_depth) in synonyms.chain(children) {
+                    if seen.insert(related_tag.clone()) {
+                        expanded.push(related_tag.clone());
+                        next_level.insert(related_tag, related_depth);
+                    }
+                }
+            }
+
+            level = next_level;
+        }
+
+        expanded.truncate(MAX_EXPANDED_TAGS);
+
+        Ok(expanded)
+    }
+}
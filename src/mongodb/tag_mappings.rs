@@ -0,0 +1,57 @@
+use crate::mongodb::TagMapping;
+use anyhow::Result;
+use futures::TryStreamExt;
+use mongodb::{Collection, bson::doc};
+use std::{collections::HashMap, fmt::Display};
+
+#[derive(Debug)]
+pub struct TagMappings {
+    collection: Collection<TagMapping>,
+}
+
+impl TagMappings {
+    pub fn new(collection: Collection<TagMapping>) -> Self {
+        Self { collection }
+    }
+
+    pub async fn get<T: Display>(&self, name: T) -> Result<Option<TagMapping>> {
+        let name = name.to_string().to_lowercase();
+        Ok(self.collection.find_one(doc! { "_id": name, "deleted": { "$ne": true } }).await?)
+    }
+
+    pub async fn find(&self) -> Result<Vec<TagMapping>> {
+        Ok(self.collection.find(doc! { "deleted": { "$ne": true } }).sort(doc! { "_id": 1 }).await?.try_collect().await?)
+    }
+
+    // Returns every mapping as a normalized tag -> pixiv tags HashMap, like in the config
+    pub async fn to_hashmap(&self) -> Result<HashMap<String, Vec<String>>> {
+        Ok(self.find().await?.into_iter().map(|tag_mapping| (tag_mapping.name, tag_mapping.pixiv_tags)).collect())
+    }
+
+    pub async fn set<T: Display>(&self, name: T, pixiv_tags: &[String]) -> Result<()> {
+        let name = name.to_string().to_lowercase();
+        let update = doc! { "$set": { "pixivTags": pixiv_tags }, "$unset": { "deleted": "" } };
+        self.collection.update_one(doc! { "_id": name }, update).upsert(true).await?;
+        Ok(())
+    }
+
+    // Inserts the mappings that don't exist yet, so mappings edited or deleted through the API aren't overwritten by the config
+    pub async fn seed(&self, mappings: &HashMap<String, Vec<String>>) -> Result<()> {
+        for (name, pixiv_tags) in mappings {
+            let name = name.to_lowercase();
+            let update = doc! { "$setOnInsert": { "pixivTags": pixiv_tags } };
+            self.collection.update_one(doc! { "_id": name }, update).upsert(true).await?;
+        }
+
+        Ok(())
+    }
+
+    // Deleted mappings are kept as tombstones, which stops them from being seeded again
+    pub async fn delete<T: Display>(&self, name: T) -> Result<bool> {
+        let name = name.to_string().to_lowercase();
+        let result =
+            self.collection.update_one(doc! { "_id": name, "deleted": { "$ne": true } }, doc! { "$set": { "deleted": true } }).await?;
+
+        Ok(result.modified_count != 0)
+    }
+}
@@ -0,0 +1,84 @@
+use crate::mongodb::{TagTranslationJob, TagTranslationJobStatus};
+use anyhow::Result;
+use chrono::{TimeDelta, Utc};
+use futures::TryStreamExt;
+use mongodb::{Collection, bson::doc};
+use std::fmt::Display;
+
+// Jobs are marked as failed after this many attempts and won't be retried anymore
+const MAX_ATTEMPTS: u32 = 5;
+
+#[derive(Debug)]
+pub struct TagTranslationQueue {
+    collection: Collection<TagTranslationJob>,
+}
+
+impl TagTranslationQueue {
+    pub fn new(collection: Collection<TagTranslationJob>) -> Self {
+        Self { collection }
+    }
+
+    // Tags that are already queued are left as is, so this can be called as often as needed
+    pub async fn enqueue<T: Display>(&self, ids: &[T]) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+
+        for id in ids {
+            let id = id.to_string().to_lowercase();
+            let update = doc! { "$setOnInsert": { "status": "pending", "attempts": 0, "enqueuedAt": &now, "nextAttemptAt": &now } };
+            self.collection.update_one(doc! { "_id": id }, update).upsert(true).await?;
+        }
+
+        Ok(())
+    }
+
+    // Like `enqueue`, but also makes failed jobs pending again
+    pub async fn requeue<T: Display>(&self, ids: &[T]) -> Result<()> {
+        let ids = ids.iter().map(|id| id.to_string().to_lowercase()).collect::<Vec<String>>();
+        let update = doc! { "$set": { "status": "pending", "attempts": 0, "nextAttemptAt": Utc::now().to_rfc3339() }, "$unset": { "lastError": "" } };
+        self.collection.update_many(doc! { "_id": { "$in": &ids } }, update).await?;
+        self.enqueue(&ids).await
+    }
+
+    // Returns the oldest pending job that is due
+    pub async fn next(&self) -> Result<Option<TagTranslationJob>> {
+        let filter = doc! { "status": "pending", "nextAttemptAt": { "$lte": Utc::now().to_rfc3339() } };
+        Ok(self.collection.find_one(filter).sort(doc! { "enqueuedAt": 1 }).await?)
+    }
+
+    pub async fn complete<T: Display>(&self, id: T) -> Result<()> {
+        self.collection.delete_one(doc! { "_id": id.to_string() }).await?;
+        Ok(())
+    }
+
+    // Retries the job later with an exponential backoff, or marks it as failed if it ran out of attempts
+    pub async fn fail<T: Display>(&self, job: &TagTranslationJob, error: T) -> Result<()> {
+        let attempts = job.attempts + 1;
+        let status = if attempts >= MAX_ATTEMPTS { "failed" } else { "pending" };
+        let next_attempt_at = Utc::now() + TimeDelta::seconds(30 * 2_i64.pow(attempts));
+
+        let update = doc! {
+            "$set": {
+                "status": status,
+                "attempts": attempts,
+                "nextAttemptAt": next_attempt_at.to_rfc3339(),
+                "lastError": error.to_string(),
+            },
+        };
+
+        self.collection.update_one(doc! { "_id": &job.id }, update).await?;
+        Ok(())
+    }
+
+    pub async fn count(&self, status: TagTranslationJobStatus) -> Result<u64> {
+        let status = match status {
+            TagTranslationJobStatus::Pending => "pending",
+            TagTranslationJobStatus::Failed => "failed",
+        };
+
+        Ok(self.collection.count_documents(doc! { "status": status }).await?)
+    }
+
+    pub async fn find(&self, limit: i64) -> Result<Vec<TagTranslationJob>> {
+        Ok(self.collection.find(doc! {}).sort(doc! { "status": 1, "nextAttemptAt": 1 }).limit(limit).await?.try_collect().await?)
+    }
+}
@@ -1,8 +1,16 @@
-use crate::{CONFIG, REQWEST, USER_AGENT};
-use anyhow::Result;
-use serde::{Deserialize, Serialize};
+use crate::{CONFIG, METRICS, REQWEST, USER_AGENT};
+use anyhow::{Context, Result, bail};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
 use serde_with::{VecSkipError, serde_as};
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    sync::RwLock,
+};
+use utoipa::ToSchema;
 
 // pixiv's hard limit is 100
 pub const PIXIV_BOOKMARKS_PER_PAGE: i64 = 100;
@@ -17,7 +25,7 @@ impl PixivBookmarks {
         let offset = (page - 1) * PIXIV_BOOKMARKS_PER_PAGE;
 
         let res = REQWEST
-            .get(format!("https://www.pixiv.net/ajax/user/{}/illusts/bookmarks", CONFIG.pixiv_user_id))
+            .get(format!("https://www.pixiv.net/ajax/user/{}/illusts/bookmarks", CONFIG.load().pixiv_user_id))
             .query(&[
                 ("offset", offset.to_string()),
                 ("limit", PIXIV_BOOKMARKS_PER_PAGE.to_string()),
@@ -25,14 +33,86 @@ impl PixivBookmarks {
                 ("tag", tag.to_string()),
             ])
             .header("user-agent", USER_AGENT)
-            .header("cookie", format!("PHPSESSID={}", CONFIG.pixiv_phpsessid))
-            .send()
+            .header("cookie", format!("PHPSESSID={}", CONFIG.load().pixiv_phpsessid))
+            .send_counted()
+            .await?;
+
+        let mut bookmarks = res.json::<Self>().await?;
+
+        for work in &mut bookmarks.body.works {
+            if let Some(bookmark_data) = &work.bookmark_data {
+                work.user_tags = bookmarks.body.bookmark_tags.get(&bookmark_data.id).cloned().unwrap_or_default();
+            }
+        }
+
+        Ok(bookmarks)
+    }
+
+    pub async fn add<T: Display>(illust_id: T) -> Result<()> {
+        let body = json!({ "illust_id": illust_id.to_string(), "restrict": 0, "comment": "", "tags": [] });
+        let req = REQWEST.post("https://www.pixiv.net/ajax/illusts/bookmarks/add").json(&body);
+        pixiv_write::<serde_json::Value>(req).await?;
+        Ok(())
+    }
+
+    // This takes the bookmark ID (from the bookmark data), not the artwork ID
+    pub async fn delete<T: Display>(bookmark_id: T) -> Result<()> {
+        let req = REQWEST.post("https://www.pixiv.net/ajax/illusts/bookmarks/delete").form(&[("bookmark_id", bookmark_id.to_string())]);
+        pixiv_write::<serde_json::Value>(req).await?;
+        Ok(())
+    }
+
+    pub async fn add_user_tags<T: Display, U: Display>(bookmark_ids: &[T], tags: &[U]) -> Result<()> {
+        let bookmark_ids = bookmark_ids.iter().map(|bookmark_id| bookmark_id.to_string()).collect::<Vec<String>>();
+        let tags = tags.iter().map(|tag| tag.to_string()).collect::<Vec<String>>();
+        let req = REQWEST
+            .post("https://www.pixiv.net/ajax/illusts/bookmarks/add_tags")
+            .json(&json!({ "tags": tags, "bookmarkIds": bookmark_ids }));
+        pixiv_write::<serde_json::Value>(req).await?;
+        Ok(())
+    }
+
+    pub async fn remove_user_tags<T: Display, U: Display>(bookmark_ids: &[T], tags: &[U]) -> Result<()> {
+        let bookmark_ids = bookmark_ids.iter().map(|bookmark_id| bookmark_id.to_string()).collect::<Vec<String>>();
+        let tags = tags.iter().map(|tag| tag.to_string()).collect::<Vec<String>>();
+        let req = REQWEST
+            .post("https://www.pixiv.net/ajax/illusts/bookmarks/remove_tags")
+            .json(&json!({ "removeTags": tags, "bookmarkIds": bookmark_ids }));
+        pixiv_write::<serde_json::Value>(req).await?;
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PixivUserTags {
+    pub body: PixivUserTagsBody,
+}
+
+impl PixivUserTags {
+    pub async fn get() -> Result<Self> {
+        let res = REQWEST
+            .get(format!("https://www.pixiv.net/ajax/user/{}/illusts/bookmark/tags", CONFIG.load().pixiv_user_id))
+            .header("user-agent", USER_AGENT)
+            .header("cookie", format!("PHPSESSID={}", CONFIG.load().pixiv_phpsessid))
+            .send_counted()
             .await?;
 
         Ok(res.json().await?)
     }
 }
 
+#[derive(Deserialize, Debug)]
+pub struct PixivUserTagsBody {
+    // We only sync public bookmarks, so private tags are ignored
+    pub public: Vec<PixivUserTagsBodyTag>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PixivUserTagsBodyTag {
+    pub tag: String,
+    pub cnt: u64,
+}
+
 #[serde_as]
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PixivBookmarkPageBody {
@@ -41,9 +121,13 @@ pub struct PixivBookmarkPageBody {
     pub works: Vec<PixivBookmarkPageBodyWork>,
 
     pub total: u64,
+
+    // The user's own bookmark tags, keyed by bookmark ID. This is moved into each work's `user_tags` after fetching
+    #[serde(default, rename = "bookmarkTags", skip_serializing)]
+    pub bookmark_tags: HashMap<String, Vec<String>>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct PixivBookmarkPageBodyWork {
     #[serde(rename(serialize = "_id"), alias = "_id")]
@@ -75,15 +159,32 @@ pub struct PixivBookmarkPageBodyWork {
     pub is_masked: bool,
     pub ai_type: u64,
     pub visibility_scope: u64,
+
+    // The user's own bookmark tags, which are separate from the artwork tags
+    #[serde(default)]
+    pub user_tags: Vec<String>,
+
+    // These will be set by the bookmark validator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation_state: Option<PixivArtworkState>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_validated_at: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+impl PixivBookmarkPageBodyWork {
+    pub fn is_private(&self) -> bool {
+        self.bookmark_data.as_ref().is_some_and(|bookmark_data| bookmark_data.private)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 pub struct PixivBookmarkPageBodyWorkBookmarkData {
     pub id: String,
     pub private: bool,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct PixivBookmarkPageBodyWorkTitleCaptionTranslation {
     pub work_title: Option<String>,
@@ -101,7 +202,8 @@ impl PixivTags {
         let encoded_tag = urlencoding::encode(&tag);
 
         // The `lang` query parameter is important to ensure the "en" property is included in the tag translations
-        let res = REQWEST.get(format!("https://www.pixiv.net/ajax/search/tags/{encoded_tag}")).query(&[("lang", "en")]).send().await?;
+        let res =
+            REQWEST.get(format!("https://www.pixiv.net/ajax/search/tags/{encoded_tag}")).query(&[("lang", "en")]).send_counted().await?;
         Ok(res.json().await?)
     }
 }
@@ -115,6 +217,10 @@ pub struct PixivTagsBody {
 
 #[derive(Deserialize, Debug)]
 pub struct PixivTagsBodyBreadcrumbs {
+    // The ancestors of the tag from the top, which have the same shape as the successors
+    #[serde(default)]
+    pub current: Vec<PixivTagsBodyBreadcrumbsSuccessor>,
+
     pub successor: Vec<PixivTagsBodyBreadcrumbsSuccessor>,
 }
 
@@ -127,6 +233,18 @@ pub struct PixivTagsBodyBreadcrumbsSuccessor {
 #[derive(Deserialize, Debug)]
 pub struct PixivTagsBodyBreadcrumbsSuccessorTranslation {
     pub en: String,
+
+    #[serde(default)]
+    pub ko: Option<String>,
+
+    #[serde(default)]
+    pub zh: Option<String>,
+
+    #[serde(default)]
+    pub zh_tw: Option<String>,
+
+    #[serde(default)]
+    pub romaji: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -140,5 +258,206 @@ pub enum PixivTagsBodyTagTranslationWrapper {
 
 #[derive(Deserialize, Debug)]
 pub struct PixivTagsBodyTagTranslation {
-    pub romaji: String,
+    #[serde(default)]
+    pub en: Option<String>,
+
+    #[serde(default)]
+    pub ko: Option<String>,
+
+    #[serde(default)]
+    pub zh: Option<String>,
+
+    #[serde(default)]
+    pub zh_tw: Option<String>,
+
+    #[serde(default)]
+    pub romaji: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PixivIllust {
+    pub body: PixivIllustBody,
+}
+
+impl PixivIllust {
+    pub async fn get<T: Display>(illust_id: T) -> Result<Self> {
+        let res = REQWEST
+            .get(format!("https://www.pixiv.net/ajax/illust/{illust_id}"))
+            .header("user-agent", USER_AGENT)
+            .header("cookie", format!("PHPSESSID={}", CONFIG.load().pixiv_phpsessid))
+            .send_counted()
+            .await?;
+
+        Ok(res.json().await?)
+    }
+
+    // Checks whether an artwork can still be viewed, which only errors if pixiv couldn't be reached or is rate limiting
+    pub async fn get_state<T: Display>(illust_id: T) -> Result<PixivArtworkState> {
+        let res = REQWEST
+            .get(format!("https://www.pixiv.net/ajax/illust/{illust_id}"))
+            .header("user-agent", USER_AGENT)
+            .header("cookie", format!("PHPSESSID={}", CONFIG.load().pixiv_phpsessid))
+            .send_counted()
+            .await?;
+
+        let status = res.status();
+        let res = res.json::<PixivAjaxResponse<Value>>().await.context("pixiv returned an unexpected response")?;
+
+        if res.error {
+            if !status.is_client_error() || status == StatusCode::TOO_MANY_REQUESTS {
+                bail!(PixivError(format!("pixiv returned an error: {}", res.message)));
+            }
+
+            // pixiv uses the same status for both, so the message is the only way to tell them apart
+            let message = res.message.to_lowercase();
+
+            if message.contains("private") || message.contains("非公開") {
+                return Ok(PixivArtworkState::Private);
+            }
+
+            return Ok(PixivArtworkState::Deleted);
+        }
+
+        let body =
+            serde_json::from_value::<PixivIllustBody>(res.body.unwrap_or_default()).context("pixiv returned an unexpected response")?;
+
+        // Masked artworks are still listed, but without any image
+        Ok(match body.urls.original {
+            Some(_) => PixivArtworkState::Available,
+            None => PixivArtworkState::Masked,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PixivIllustBody {
+    pub bookmark_data: Option<PixivBookmarkPageBodyWorkBookmarkData>,
+
+    #[serde(default)]
+    pub urls: PixivIllustBodyUrls,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct PixivIllustBodyUrls {
+    pub original: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PixivArtworkState {
+    Available,
+    Deleted,
+    Private,
+    Masked,
+}
+
+// An error returned by pixiv itself, as opposed to pixiv not being reachable
+#[derive(Debug)]
+pub struct PixivError(pub String);
+
+impl Display for PixivError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for PixivError {}
+
+#[derive(Deserialize, Debug)]
+struct PixivAjaxResponse<T> {
+    error: bool,
+
+    #[serde(default)]
+    message: String,
+
+    body: Option<T>,
+}
+
+// pixiv rejects write requests without the CSRF token that is embedded in its pages for the logged-in session
+pub async fn get_csrf_token() -> Result<String> {
+    let html = REQWEST
+        .get("https://www.pixiv.net/")
+        .header("user-agent", USER_AGENT)
+        .header("cookie", format!("PHPSESSID={}", CONFIG.load().pixiv_phpsessid))
+        .send_counted()
+        .await?
+        .text()
+        .await?;
+
+    // The token is either in the global data meta tag or in the escaped Next.js data, depending on the page version
+    for pattern in [r#""token":""#, r#"\"token\":\""#] {
+        if let Some(start) = html.find(pattern).map(|index| index + pattern.len()) {
+            let token = html[start..].chars().take_while(|char| char.is_ascii_alphanumeric()).collect::<String>();
+
+            if !token.is_empty() {
+                return Ok(token);
+            }
+        }
+    }
+
+    bail!(PixivError("Could not find a CSRF token on pixiv. The session might be invalid.".into()))
+}
+
+// Counts the requests to pixiv by status, or "error" if pixiv couldn't be reached
+trait SendCounted {
+    async fn send_counted(self) -> reqwest::Result<Response>;
+}
+
+impl SendCounted for RequestBuilder {
+    async fn send_counted(self) -> reqwest::Result<Response> {
+        let result = self.send().await;
+        let status = result.as_ref().map(|res| res.status().as_str().to_string()).unwrap_or_else(|_| "error".into());
+
+        METRICS.pixiv_requests.with_label_values(&[&status]).inc();
+        result
+    }
+}
+
+// The CSRF token along with the session it's for, so it isn't fetched again for every write
+static CSRF_TOKEN: RwLock<Option<(String, String)>> = RwLock::new(None);
+
+async fn get_cached_csrf_token(refresh: bool) -> Result<String> {
+    let phpsessid = CONFIG.load().pixiv_phpsessid.to_string();
+
+    if !refresh
+        && let Some((session, token)) = CSRF_TOKEN.read().unwrap().as_ref()
+        && session == &phpsessid
+    {
+        return Ok(token.clone());
+    }
+
+    let token = get_csrf_token().await?;
+    *CSRF_TOKEN.write().unwrap() = Some((phpsessid, token.clone()));
+
+    Ok(token)
+}
+
+async fn send_write(req: RequestBuilder, csrf_token: String) -> Result<Response> {
+    Ok(req
+        .header("user-agent", USER_AGENT)
+        .header("cookie", format!("PHPSESSID={}", CONFIG.load().pixiv_phpsessid))
+        .header("referer", "https://www.pixiv.net/")
+        .header("x-csrf-token", csrf_token)
+        .send_counted()
+        .await?)
+}
+
+async fn pixiv_write<T: DeserializeOwned>(req: RequestBuilder) -> Result<Option<T>> {
+    let retry = req.try_clone();
+    let res = send_write(req, get_cached_csrf_token(false).await?).await?;
+
+    // pixiv rejects expired tokens, so the token is fetched again and the write is retried once
+    let res = match (res.status(), retry) {
+        (StatusCode::BAD_REQUEST | StatusCode::FORBIDDEN, Some(retry)) => send_write(retry, get_cached_csrf_token(true).await?).await?,
+        _ => res,
+    };
+
+    let res = res.json::<PixivAjaxResponse<T>>().await.context("pixiv returned an unexpected response")?;
+
+    if res.error {
+        bail!(PixivError(format!("pixiv returned an error: {}", res.message)));
+    }
+
+    Ok(res.body)
 }
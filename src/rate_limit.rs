@@ -0,0 +1,207 @@
+use crate::{CONFIG, RATE_LIMITER, auth::Scopes, mongodb::ApiTokenScope, routes::ApiError, server::ClientAddr};
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::{HeaderValue, StatusCode, header::RETRY_AFTER, request::Parts},
+    middleware::{Next, from_fn_with_state},
+    response::{IntoResponse, Response},
+    routing::MethodRouter,
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::time::sleep;
+
+const CLEAN_RATE_LIMITS_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Debug)]
+pub struct RateLimits {
+    // Uses the first address in X-Forwarded-For as the client IP, which should only be enabled behind a reverse proxy
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+
+    // Routes that only read from the database
+    #[serde(default = "RateLimits::default_read")]
+    pub read: RateLimitQuota,
+
+    // Routes that only write to the database
+    #[serde(default = "RateLimits::default_write")]
+    pub write: RateLimitQuota,
+
+    // Routes that send requests to pixiv, which are limited the most so pixiv can't be hammered through the API
+    #[serde(default = "RateLimits::default_pixiv")]
+    pub pixiv: RateLimitQuota,
+}
+
+impl RateLimits {
+    fn default_read() -> RateLimitQuota {
+        RateLimitQuota { requests: 120, per_seconds: 60 }
+    }
+
+    fn default_write() -> RateLimitQuota {
+        RateLimitQuota { requests: 30, per_seconds: 60 }
+    }
+
+    fn default_pixiv() -> RateLimitQuota {
+        RateLimitQuota { requests: 10, per_seconds: 60 }
+    }
+
+    fn quota(&self, class: RateLimitClass) -> RateLimitQuota {
+        match class {
+            RateLimitClass::Read => self.read,
+            RateLimitClass::Write => self.write,
+            RateLimitClass::Pixiv => self.pixiv,
+        }
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self { trust_forwarded_for: false, read: Self::default_read(), write: Self::default_write(), pixiv: Self::default_pixiv() }
+    }
+}
+
+// Allows bursts of up to `requests`, refilling at `requests` per `per_seconds`
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct RateLimitQuota {
+    pub requests: u32,
+    pub per_seconds: u64,
+}
+
+impl RateLimitQuota {
+    fn refill_per_second(&self) -> f64 {
+        self.requests as f64 / self.per_seconds as f64
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum RateLimitClass {
+    Read,
+    Write,
+    Pixiv,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+// Token buckets for every client and route class. Clients are API tokens, or IPs for anonymous requests
+#[derive(Default, Debug)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<(RateLimitClass, String), TokenBucket>>,
+}
+
+impl RateLimiter {
+    // Takes a token from the client's bucket, or returns how long to wait until there is one
+    fn take(&self, class: RateLimitClass, client: String, quota: RateLimitQuota) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+        let bucket = buckets.entry((class, client)).or_insert(TokenBucket { tokens: quota.requests as f64, refilled_at: now });
+
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * quota.refill_per_second()).min(quota.requests as f64);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1. {
+            bucket.tokens -= 1.;
+            return Ok(());
+        }
+
+        Err(Duration::from_secs_f64((1. - bucket.tokens) / quota.refill_per_second()))
+    }
+
+    // Buckets that would be full by now are the same as new ones, so they're removed to keep the map small
+    fn clean(&self) {
+        let rate_limits = &CONFIG.load().rate_limits;
+        let now = Instant::now();
+
+        self.buckets.lock().unwrap().retain(|(class, _), bucket| {
+            let quota = rate_limits.quota(*class);
+            let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * quota.refill_per_second();
+            bucket.tokens + refill < quota.requests as f64
+        });
+    }
+}
+
+pub async fn clean_rate_limits() {
+    loop {
+        sleep(CLEAN_RATE_LIMITS_INTERVAL).await;
+        RATE_LIMITER.clean();
+    }
+}
+
+// The client's bucket for the route's class, which handlers can take more tokens from if a request costs more than one
+// Requests that aren't rate limited have no client, and always have tokens left
+#[derive(Clone, Default, Debug)]
+pub struct RateLimitBudget {
+    class: Option<RateLimitClass>,
+    client: Option<String>,
+}
+
+impl RateLimitBudget {
+    pub fn take(&self) -> Result<(), Duration> {
+        match (self.class, &self.client) {
+            (Some(class), Some(client)) => RATE_LIMITER.take(class, client.clone(), CONFIG.load().rate_limits.quota(class)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for RateLimitBudget {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Self>().cloned().unwrap_or_default())
+    }
+}
+
+async fn rate_limit(State(class): State<RateLimitClass>, mut req: Request, next: Next) -> Response {
+    let config = CONFIG.load();
+    let scopes = req.extensions().get::<Scopes>().cloned().unwrap_or_default();
+
+    // Admin tokens are trusted not to abuse the API
+    if scopes.token_id().is_some() && scopes.has(ApiTokenScope::Admin) {
+        return next.run(req).await;
+    }
+
+    let client = match scopes.token_id() {
+        Some(token_id) => format!("token:{token_id}"),
+        None => format!("ip:{}", client_ip(&req, config.rate_limits.trust_forwarded_for).unwrap_or_else(|| "unknown".into())),
+    };
+
+    let budget = RateLimitBudget { class: Some(class), client: Some(client) };
+
+    if let Err(retry_after) = budget.take() {
+        let retry_after = retry_after.as_secs_f64().ceil() as u64;
+        let mut response =
+            ApiError::new(StatusCode::TOO_MANY_REQUESTS, "rate_limited", format!("Too many requests. Try again in {retry_after} seconds."))
+                .into_response();
+
+        response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        return response;
+    }
+
+    req.extensions_mut().insert(budget);
+    next.run(req).await
+}
+
+fn client_ip(req: &Request, trust_forwarded_for: bool) -> Option<String> {
+    if trust_forwarded_for
+        && let Some(forwarded_for) = req.headers().get("x-forwarded-for").and_then(|value| value.to_str().ok())
+        && let Some(ip) = forwarded_for.split(',').map(str::trim).find(|ip| !ip.is_empty())
+    {
+        return Some(ip.into());
+    }
+
+    req.extensions().get::<ConnectInfo<ClientAddr>>().and_then(|ConnectInfo(ClientAddr(addr))| addr.map(|addr| addr.ip().to_string()))
+}
+
+// Limits how often each client can request the routes, with a separate budget for every class
+pub fn limit(class: RateLimitClass, method_router: MethodRouter) -> MethodRouter {
+    method_router.route_layer(from_fn_with_state(class, rate_limit))
+}
@@ -0,0 +1,26 @@
+use crate::{
+    MONGODB,
+    mongodb::BookmarkTagNameChange,
+    routes::{ApiError, DataResponse, Path, Response},
+};
+use tracing::error;
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/bookmark-tags/{id}/history",
+    tag = "admin",
+    params(("id" = String, Path, description = "The bookmark tag")),
+    responses((status = 200, body = inline(DataResponse<Vec<BookmarkTagNameChange>>)), (status = "default", body = ApiError)),
+    security(("api_token" = [])),
+)]
+pub async fn handler(id: Path<String>) -> Response<Vec<BookmarkTagNameChange>> {
+    let mongodb = MONGODB.get().unwrap();
+
+    match mongodb.bookmarks.tags.get_name_history(&*id).await {
+        Ok(history) => Response::Data(history),
+        Err(error) => {
+            error!(r#"An error occurred while trying to get the name history of bookmark tag "{}": {error:?}"#, *id);
+            Response::Error(error.into())
+        },
+    }
+}
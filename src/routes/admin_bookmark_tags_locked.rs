@@ -0,0 +1,39 @@
+use crate::{
+    MONGODB, RESPONSE_CACHE,
+    routes::{ApiError, DataResponse, Json, Path, Response},
+};
+use serde::Deserialize;
+use tracing::error;
+use utoipa::ToSchema;
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/bookmark-tags/{id}/locked",
+    tag = "admin",
+    params(("id" = String, Path, description = "The bookmark tag")),
+    request_body = LockedBody,
+    responses((status = 200, body = inline(DataResponse<bool>)), (status = "default", body = ApiError)),
+    security(("api_token" = [])),
+)]
+pub async fn handler(id: Path<String>, body: Json<LockedBody>) -> Response<bool> {
+    let mongodb = MONGODB.get().unwrap();
+
+    match mongodb.bookmarks.tags.set_locked(&*id, body.locked).await {
+        Ok(exists) => {
+            if exists {
+                RESPONSE_CACHE.invalidate();
+            }
+
+            Response::Data(exists)
+        },
+        Err(error) => {
+            error!(r#"An error occurred while trying to lock bookmark tag "{}": {error:?}"#, *id);
+            Response::Error(error.into())
+        },
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct LockedBody {
+    locked: bool,
+}
@@ -0,0 +1,57 @@
+use crate::{
+    MONGODB, RESPONSE_CACHE,
+    routes::{ApiError, DataResponse, Json, Path, Response},
+};
+use serde::Deserialize;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/bookmark-tags/{id}/name",
+    tag = "admin",
+    params(("id" = String, Path, description = "The bookmark tag")),
+    request_body = NameBody,
+    responses((status = 200, body = inline(DataResponse<bool>)), (status = "default", body = ApiError)),
+    security(("api_token" = [])),
+)]
+pub async fn handler(id: Path<String>, body: Json<NameBody>) -> Response<bool> {
+    let mongodb = MONGODB.get().unwrap();
+
+    // Names are used as search terms, which are split by whitespace
+    let name = body.name.split_whitespace().collect::<Vec<&str>>().join("_");
+
+    if name.is_empty() {
+        return Response::Error(ApiError::bad_request("invalid_name", "Tag names must not be empty."));
+    }
+
+    match mongodb.bookmarks.tags.set_name_manually(&*id, &name, body.locked).await {
+        Ok(exists) => {
+            if exists {
+                info!(r#"Bookmark tag "{}" was renamed to "{name}"."#, *id);
+                RESPONSE_CACHE.invalidate();
+            }
+
+            Response::Data(exists)
+        },
+        Err(error) => {
+            error!(r#"An error occurred while trying to rename bookmark tag "{}": {error:?}"#, *id);
+            Response::Error(error.into())
+        },
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NameBody {
+    name: String,
+
+    // Manual names are locked by default so they aren't replaced by the next automatic translation
+    #[serde(default = "NameBody::default_locked")]
+    locked: bool,
+}
+
+impl NameBody {
+    fn default_locked() -> bool {
+        true
+    }
+}
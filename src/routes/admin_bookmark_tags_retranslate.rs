@@ -0,0 +1,43 @@
+use crate::{
+    MONGODB,
+    routes::{ApiError, DataResponse, Json, Response},
+};
+use serde::Deserialize;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/bookmark-tags/retranslate",
+    tag = "admin",
+    request_body = RetranslateBody,
+    responses((status = 200, body = inline(DataResponse<u64>)), (status = "default", body = ApiError)),
+    security(("api_token" = [])),
+)]
+pub async fn handler(body: Json<RetranslateBody>) -> Response<u64> {
+    let mongodb = MONGODB.get().unwrap();
+
+    let count = match mongodb.bookmarks.tags.unset_translated(&body.ids).await {
+        Ok(count) => count,
+        Err(error) => {
+            error!("An error occurred while trying to reset bookmark tag translations: {error:?}");
+            return Response::Error(error.into());
+        },
+    };
+
+    match mongodb.bookmarks.tags.queue.requeue(&body.ids).await {
+        Ok(()) => {
+            info!("{count} {} queued for retranslation.", if count == 1 { "bookmark tag was" } else { "bookmark tags were" });
+            Response::Data(count)
+        },
+        Err(error) => {
+            error!("An error occurred while trying to queue bookmark tags for retranslation: {error:?}");
+            Response::Error(error.into())
+        },
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RetranslateBody {
+    ids: Vec<String>,
+}
@@ -0,0 +1,46 @@
+use crate::{
+    MONGODB,
+    mongodb::{TagTranslationJob, TagTranslationJobStatus},
+    routes::{ApiError, DataResponse, Response},
+};
+use anyhow::Result;
+use serde::Serialize;
+use tracing::error;
+use utoipa::ToSchema;
+
+// The number of jobs included in the status
+const TAG_QUEUE_JOBS_LIMIT: i64 = 50;
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/tag-queue",
+    tag = "admin",
+    responses((status = 200, body = inline(DataResponse<TagQueueStatus>)), (status = "default", body = ApiError)),
+    security(("api_token" = [])),
+)]
+pub async fn handler() -> Response<TagQueueStatus> {
+    match get_tag_queue_status().await {
+        Ok(status) => Response::Data(status),
+        Err(error) => {
+            error!("An error occurred while trying to get the tag translation queue status: {error:?}");
+            Response::Error(error.into())
+        },
+    }
+}
+
+async fn get_tag_queue_status() -> Result<TagQueueStatus> {
+    let mongodb = MONGODB.get().unwrap();
+
+    Ok(TagQueueStatus {
+        pending: mongodb.bookmarks.tags.queue.count(TagTranslationJobStatus::Pending).await?,
+        failed: mongodb.bookmarks.tags.queue.count(TagTranslationJobStatus::Failed).await?,
+        jobs: mongodb.bookmarks.tags.queue.find(TAG_QUEUE_JOBS_LIMIT).await?,
+    })
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TagQueueStatus {
+    pending: u64,
+    failed: u64,
+    jobs: Vec<TagTranslationJob>,
+}
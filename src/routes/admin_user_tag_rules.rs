@@ -0,0 +1,62 @@
+use crate::{
+    routes::{ApiError, DataResponse, Query, Response},
+    rules::{UserTagRuleResult, apply_user_tag_rules},
+};
+use serde::Deserialize;
+use tokio::spawn;
+use tracing::error;
+use utoipa::IntoParams;
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/user-tag-rules/apply",
+    tag = "admin",
+    params(ApplyQuery),
+    responses(
+        (status = 200, description = "The results of the dry run", body = inline(DataResponse<Vec<UserTagRuleResult>>)),
+        (status = 202, description = "The bookmarks the rules are being applied to", body = inline(DataResponse<Vec<UserTagRuleResult>>)),
+        (status = "default", body = ApiError),
+    ),
+    security(("api_token" = [])),
+)]
+pub async fn handler(query: Query<ApplyQuery>) -> Response<Vec<UserTagRuleResult>> {
+    // The dry run shows what will be applied, which is done in the background as pushing to pixiv can outlast the request timeout
+    let results = match apply_user_tag_rules(None, true, false).await {
+        Ok(results) => results,
+        Err(error) => {
+            error!("An error occurred while trying to apply user tag rules: {error:?}");
+            return Response::Error(error.into());
+        },
+    };
+
+    if query.dry_run {
+        return Response::Data(results);
+    }
+
+    let push = query.push;
+
+    spawn(async move {
+        if let Err(error) = apply_user_tag_rules(None, false, push).await {
+            error!("An error occurred while trying to apply user tag rules: {error:?}");
+        }
+    });
+
+    Response::Accepted(results)
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ApplyQuery {
+    // Defaults to a dry run so nothing is written by accident
+    #[serde(default = "ApplyQuery::default_dry_run")]
+    dry_run: bool,
+
+    // User tags that aren't pushed to pixiv will be lost on the next user tag sync
+    #[serde(default)]
+    push: bool,
+}
+
+impl ApplyQuery {
+    fn default_dry_run() -> bool {
+        true
+    }
+}
@@ -1,26 +1,104 @@
-use crate::{MONGODB, mongodb::BookmarkTag, routes::Response};
-use axum::{Json, extract::Query};
+use crate::{
+    MONGODB,
+    auth::Scopes,
+    mongodb::{ApiTokenScope, BookmarkTag, TagLanguage},
+    routes::{ApiError, DataResponse, Query, Response, bookmarks::get_visibility_filter},
+};
+use anyhow::Result;
+use mongodb::bson::{Document, doc};
 use serde::Deserialize;
+use std::cmp::Reverse;
 use tracing::error;
+use utoipa::IntoParams;
 
-pub async fn handler(query: Query<TagQuery>) -> Json<Response<Vec<BookmarkTag>>> {
+#[utoipa::path(
+    get,
+    path = "/api/bookmark-tags",
+    tag = "bookmark tags",
+    params(TagQuery),
+    responses((status = 200, body = inline(DataResponse<Vec<BookmarkTag>>)), (status = "default", body = ApiError)),
+    security((), ("api_token" = [])),
+)]
+pub async fn handler(scopes: Scopes, query: Query<TagQuery>) -> Response<Vec<BookmarkTag>> {
     let mongodb = MONGODB.get().unwrap();
 
-    match mongodb.bookmarks.tags.find(&query.query).await {
-        Ok(mut bookmark_tags) => {
-            let total = mongodb.bookmarks.count(None).await.unwrap_or(0);
-            bookmark_tags.insert(0, BookmarkTag { id: "すべて".into(), name: Some("all".into()), total });
-            Json(Response::Data(bookmark_tags))
-        },
+    let mut bookmark_tags = match mongodb.bookmarks.tags.find(&query.query).await {
+        Ok(bookmark_tags) => bookmark_tags,
         Err(error) => {
             error!("An error occurred while trying to get bookmark tags: {error:?}");
-            Json(Response::Error(format!("{error:?}")))
+            return Response::Error(error.into());
+        },
+    };
+
+    if query.lang.is_some() {
+        for bookmark_tag in &mut bookmark_tags {
+            bookmark_tag.name = Some(bookmark_tag.display_name(query.lang));
+        }
+    }
+
+    // Private bookmarks are left out of the counted totals unless the caller can read them
+    let filter = get_visibility_filter(None, scopes.has(ApiTokenScope::ReadPrivate));
+    let (total, mapped_bookmark_tags) = match get_mapped_bookmark_tags(&query.query, filter).await {
+        Ok(result) => result,
+        Err(error) => {
+            error!("An error occurred while trying to get tag mappings and bookmark totals: {error:?}");
+            return Response::Error(error.into());
         },
+    };
+
+    // The pixiv tags of a mapping are shown as one combined entry instead
+    bookmark_tags.retain(|bookmark_tag| !mapped_bookmark_tags.iter().any(|mapped| mapped.aliases.contains(&bookmark_tag.id)));
+    bookmark_tags.extend(mapped_bookmark_tags);
+    bookmark_tags.sort_by_key(|bookmark_tag| Reverse(bookmark_tag.total));
+
+    bookmark_tags.insert(0, BookmarkTag { id: "すべて".into(), name: Some("all".into()), total, ..Default::default() });
+
+    Response::Data(bookmark_tags)
+}
+
+// Returns the number of visible bookmarks and the tag mappings matching the query as bookmark tags, with the total being the number of visible bookmarks having any of the pixiv tags
+async fn get_mapped_bookmark_tags(query: &str, filter: Option<Document>) -> Result<(u64, Vec<BookmarkTag>)> {
+    let mongodb = MONGODB.get().unwrap();
+    let query = query.to_lowercase();
+    let words = query.split_whitespace().collect::<Vec<&str>>();
+    let mut mapped_bookmark_tags = vec![];
+
+    for tag_mapping in mongodb.tag_mappings.find().await? {
+        let aliases = tag_mapping.pixiv_tags.iter().map(|pixiv_tag| pixiv_tag.to_lowercase()).collect::<Vec<String>>();
+
+        let matches = words.is_empty()
+            || words.iter().any(|word| tag_mapping.name.contains(word) || aliases.iter().any(|alias| alias.contains(word)));
+
+        if !matches {
+            continue;
+        }
+
+        mapped_bookmark_tags.push(BookmarkTag {
+            id: tag_mapping.name.clone(),
+            name: Some(tag_mapping.name),
+            aliases,
+            ..Default::default()
+        });
     }
+
+    // The first count is the overall total, the rest are the mappings in order
+    let sub_filters = std::iter::once(doc! {})
+        .chain(mapped_bookmark_tags.iter().map(|mapped| doc! { "tags": { "$in": &mapped.aliases } }))
+        .collect::<Vec<Document>>();
+    let counts = mongodb.bookmarks.count_each(filter, &sub_filters).await?;
+
+    for (mapped_bookmark_tag, total) in mapped_bookmark_tags.iter_mut().zip(counts.iter().skip(1)) {
+        mapped_bookmark_tag.total = *total;
+    }
+
+    Ok((counts.first().copied().unwrap_or(0), mapped_bookmark_tags))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
 pub struct TagQuery {
     #[serde(default)]
     query: String,
+
+    #[serde(default)]
+    lang: Option<TagLanguage>,
 }
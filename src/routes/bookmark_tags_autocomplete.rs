@@ -0,0 +1,78 @@
+use crate::{
+    MONGODB,
+    mongodb::{AutocompleteCursor, BookmarkTag, TagLanguage},
+    routes::{ApiError, DataResponse, Query, Response},
+};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+
+const AUTOCOMPLETE_MAX_LIMIT: usize = 100;
+
+#[utoipa::path(
+    get,
+    path = "/api/bookmark-tags/autocomplete",
+    tag = "bookmark tags",
+    params(AutocompleteQuery),
+    responses((status = 200, body = inline(DataResponse<AutocompleteResults>)), (status = "default", body = ApiError)),
+    security((), ("api_token" = [])),
+)]
+pub async fn handler(query: Query<AutocompleteQuery>) -> Response<AutocompleteResults> {
+    let mongodb = MONGODB.get().unwrap();
+
+    let cursor = match &query.cursor {
+        Some(cursor) => match AutocompleteCursor::decode(cursor) {
+            Some(cursor) => Some(cursor),
+            None => return Response::Error(ApiError::bad_request("invalid_cursor", "Invalid cursor.")),
+        },
+        None => None,
+    };
+
+    let limit = query.limit.clamp(1, AUTOCOMPLETE_MAX_LIMIT);
+
+    match mongodb.bookmarks.tags.autocomplete(&query.query, cursor.as_ref(), limit).await {
+        Ok((mut tags, next)) => {
+            if query.lang.is_some() {
+                for tag in &mut tags {
+                    tag.name = Some(tag.display_name(query.lang));
+                }
+            }
+
+            Response::Data(AutocompleteResults { tags, next: next.map(|cursor| cursor.encode()) })
+        },
+        Err(error) => {
+            error!("An error occurred while trying to autocomplete bookmark tags: {error:?}");
+            Response::Error(error.into())
+        },
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct AutocompleteQuery {
+    #[serde(default)]
+    query: String,
+
+    #[serde(default = "AutocompleteQuery::default_limit")]
+    limit: usize,
+
+    // Continues right after the last tag of the previous page, even if tags changed in the meantime
+    #[serde(default)]
+    cursor: Option<String>,
+
+    #[serde(default)]
+    lang: Option<TagLanguage>,
+}
+
+impl AutocompleteQuery {
+    fn default_limit() -> usize {
+        20
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AutocompleteResults {
+    tags: Vec<BookmarkTag>,
+
+    // The cursor of the next page, if there are more results
+    next: Option<String>,
+}
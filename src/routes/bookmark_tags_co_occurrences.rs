@@ -0,0 +1,96 @@
+use crate::{
+    MONGODB,
+    auth::Scopes,
+    mongodb::{ApiTokenScope, TagLanguage},
+    routes::{
+        ApiError, DataResponse, Query, Response,
+        bookmarks::{expand_tag_lists, get_tag_filter, get_visibility_filter, resolve_tag_lists},
+    },
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+
+const CO_OCCURRENCES_MAX_LIMIT: i64 = 100;
+
+#[utoipa::path(
+    get,
+    path = "/api/bookmark-tags/co-occurrences",
+    tag = "bookmark tags",
+    params(CoOccurrencesQuery),
+    responses((status = 200, body = inline(DataResponse<CoOccurrences>)), (status = "default", body = ApiError)),
+    security((), ("api_token" = [])),
+)]
+pub async fn handler(scopes: Scopes, query: Query<CoOccurrencesQuery>) -> Response<CoOccurrences> {
+    match get_co_occurrences(&query, scopes.has(ApiTokenScope::ReadPrivate)).await {
+        Ok(co_occurrences) => Response::Data(co_occurrences),
+        Err(error) => {
+            error!("An error occurred while trying to get co-occurring bookmark tags: {error:?}");
+            Response::Error(error.into())
+        },
+    }
+}
+
+async fn get_co_occurrences(query: &CoOccurrencesQuery, include_private: bool) -> Result<CoOccurrences> {
+    let mongodb = MONGODB.get().unwrap();
+    let tag_lists = resolve_tag_lists(&query.tags).await;
+
+    // The searched tags (and whatever they were resolved to) would obviously be in every result
+    // Their child tags aren't excluded though, since those are what the results can be narrowed down with
+    let searched_tags = tag_lists.iter().flatten().cloned().collect::<Vec<String>>();
+    let exclude = mongodb.tag_graph.with_synonyms(&searched_tags).await.unwrap_or(searched_tags);
+
+    let filter = get_visibility_filter(get_tag_filter(&expand_tag_lists(tag_lists).await), include_private);
+
+    let total = mongodb.bookmarks.count(filter.clone()).await?;
+    let tag_counts = mongodb.bookmarks.count_co_occurring_tags(filter, &exclude, query.limit.clamp(1, CO_OCCURRENCES_MAX_LIMIT)).await?;
+
+    let ids = tag_counts.iter().map(|tag_count| tag_count.id.clone()).collect::<Vec<String>>();
+    let bookmark_tags = mongodb.bookmarks.tags.find_by_ids(&ids).await?;
+
+    let tags = tag_counts
+        .into_iter()
+        .map(|tag_count| {
+            let bookmark_tag = bookmark_tags.iter().find(|bookmark_tag| bookmark_tag.id == tag_count.id);
+            let name = bookmark_tag.map(|bookmark_tag| bookmark_tag.display_name(query.lang)).unwrap_or_else(|| tag_count.id.clone());
+            CoOccurringTag { id: tag_count.id, name, count: tag_count.count }
+        })
+        .collect();
+
+    Ok(CoOccurrences { total, tags })
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct CoOccurrencesQuery {
+    #[serde(default)]
+    tags: String,
+
+    #[serde(default = "CoOccurrencesQuery::default_limit")]
+    limit: i64,
+
+    #[serde(default)]
+    lang: Option<TagLanguage>,
+}
+
+impl CoOccurrencesQuery {
+    fn default_limit() -> i64 {
+        30
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CoOccurrences {
+    // The number of bookmarks matching the tags
+    total: u64,
+    tags: Vec<CoOccurringTag>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CoOccurringTag {
+    #[serde(rename = "_id")]
+    id: String,
+
+    name: String,
+    count: u64,
+}
@@ -0,0 +1,68 @@
+use crate::{
+    MONGODB,
+    mongodb::BookmarkTag,
+    routes::{ApiError, DataResponse, Path, Response},
+};
+use anyhow::Result;
+use serde::Serialize;
+use tracing::error;
+use utoipa::ToSchema;
+
+#[utoipa::path(
+    get,
+    path = "/api/bookmark-tags/{id}/related",
+    tag = "bookmark tags",
+    params(("id" = String, Path, description = "The bookmark tag")),
+    responses((status = 200, body = inline(DataResponse<RelatedBookmarkTags>)), (status = "default", body = ApiError)),
+    security((), ("api_token" = [])),
+)]
+pub async fn handler(id: Path<String>) -> Response<RelatedBookmarkTags> {
+    match get_related_bookmark_tags(&id).await {
+        Ok(related) => Response::Data(related),
+        Err(error) => {
+            error!(r#"An error occurred while trying to get related bookmark tags of "{}": {error:?}"#, *id);
+            Response::Error(error.into())
+        },
+    }
+}
+
+async fn get_related_bookmark_tags(id: &str) -> Result<RelatedBookmarkTags> {
+    let mongodb = MONGODB.get().unwrap();
+    let mut node = mongodb.tag_graph.get(id).await?;
+
+    // The ID might be a translated name instead
+    if node.is_none()
+        && let Some(bookmark_tag) = mongodb.bookmarks.tags.resolve_from_name_or_id(id.to_lowercase()).await?.into_iter().next()
+    {
+        node = mongodb.tag_graph.get(&bookmark_tag.id).await?;
+    }
+
+    let Some(node) = node else { return Ok(RelatedBookmarkTags::default()) };
+
+    Ok(RelatedBookmarkTags {
+        parents: to_bookmark_tags(&node.parents).await?,
+        children: to_bookmark_tags(&node.children).await?,
+        synonyms: to_bookmark_tags(&node.synonyms).await?,
+    })
+}
+
+// Tags in the graph might not be used by any bookmark, so those are included with a total of 0
+async fn to_bookmark_tags(ids: &[String]) -> Result<Vec<BookmarkTag>> {
+    let mongodb = MONGODB.get().unwrap();
+    let mut bookmark_tags = mongodb.bookmarks.tags.find_by_ids(ids).await?;
+
+    for id in ids {
+        if !bookmark_tags.iter().any(|bookmark_tag| &bookmark_tag.id == id) {
+            bookmark_tags.push(BookmarkTag { id: id.clone(), ..Default::default() });
+        }
+    }
+
+    Ok(bookmark_tags)
+}
+
+#[derive(Serialize, Default, ToSchema)]
+pub struct RelatedBookmarkTags {
+    parents: Vec<BookmarkTag>,
+    children: Vec<BookmarkTag>,
+    synonyms: Vec<BookmarkTag>,
+}
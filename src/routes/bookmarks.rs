@@ -1,61 +1,178 @@
-use crate::{CONFIG, MONGODB, pixiv::PixivBookmarkPageBody, routes::Response};
-use axum::{Json, extract::Query};
+use crate::{
+    MONGODB,
+    auth::Scopes,
+    mongodb::{ApiTokenScope, BookmarkCursor, BookmarkFacet, BookmarkOrder, FacetBucket, TagLanguage},
+    pixiv::{PIXIV_BOOKMARKS_PER_PAGE, PixivBookmarkPageBodyWork},
+    routes::{ApiError, DataResponse, Query, Response},
+};
+use chrono::Utc;
 use mongodb::bson::{Document, doc};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
+
+#[utoipa::path(
+    get,
+    path = "/api/bookmarks",
+    tag = "bookmarks",
+    params(Pagination),
+    responses((status = 200, body = inline(DataResponse<BookmarksPage>)), (status = "default", body = ApiError)),
+    security((), ("api_token" = [])),
+)]
+pub async fn handler(scopes: Scopes, query: Query<Pagination>) -> Response<BookmarksPage> {
+    match get_bookmarks_page(&query, scopes.has(ApiTokenScope::ReadPrivate)).await {
+        Ok(page) => Response::Data(page),
+        Err(error) => Response::Error(error),
+    }
+}
+
+pub async fn get_bookmarks_page(query: &Pagination, include_private: bool) -> Result<BookmarksPage, ApiError> {
+    let mongodb = MONGODB.get().unwrap();
+    let mut facets = vec![];
+
+    for name in query.facets.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        match BookmarkFacet::from_name(name) {
+            Some(facet) => facets.push(facet),
+            None => return Err(ApiError::bad_request("unknown_facet", format!(r#"Unknown facet "{name}"."#))),
+        }
+    }
+
+    let cursor = match &query.cursor {
+        Some(cursor) => match BookmarkCursor::decode(cursor) {
+            Some(cursor) => Some(cursor),
+            None => return Err(ApiError::bad_request("invalid_cursor", "Invalid cursor.")),
+        },
+        None => None,
+    };
+
+    // A cursor always continues in its own order, so the sort parameters only matter for the first page
+    let order = match &cursor {
+        Some(cursor) => cursor.order,
+        None => BookmarkOrder {
+            sort_by: query.sort_by,
+            sort: query.sort,
+            seed: query.seed.unwrap_or_else(|| Utc::now().timestamp_subsec_nanos()),
+        },
+    };
+
+    let filter = get_visibility_filter(get_tag_filter(&get_tag_lists(&query.tags).await), include_private);
+
+    let limit = query.limit.clamp(1, PIXIV_BOOKMARKS_PER_PAGE);
+    let count = mongodb.bookmarks.count(filter.clone()).await?;
+    let bookmarks = mongodb.bookmarks.find(filter.clone(), query.offset, limit, order, cursor.as_ref(), query.lang).await?;
 
-pub async fn handler(query: Query<Pagination>) -> Json<Response<PixivBookmarkPageBody>> {
+    // A full page means there might be more bookmarks in that direction
+    // Going backwards means we came from the next page, and going forwards from an offset or cursor means there's a previous page
+    let full_page = bookmarks.len() as i64 == limit;
+    let backwards = cursor.as_ref().is_some_and(|cursor| cursor.backwards);
+    let has_next = if backwards { true } else { full_page };
+    let has_prev = if backwards { full_page } else { cursor.is_some() || query.offset != 0 };
+
+    let next = bookmarks.last().filter(|_| has_next).map(|(bookmark, value)| BookmarkCursor::new(order, bookmark, value, false).encode());
+    let prev = bookmarks.first().filter(|_| has_prev).map(|(bookmark, value)| BookmarkCursor::new(order, bookmark, value, true).encode());
+    let seed = (order.sort_by == PaginationSortBy::Random).then_some(order.seed);
+
+    let facets = if facets.is_empty() { None } else { Some(mongodb.bookmarks.facets(filter, &facets).await?) };
+
+    Ok(BookmarksPage { works: bookmarks.into_iter().map(|(bookmark, _)| bookmark).collect(), total: count, next, prev, seed, facets })
+}
+
+// Resolves each searched tag (up to 5) into the list of stored tags it matches, including mapped, translated and child tags
+pub async fn get_tag_lists(tags: &str) -> Vec<Vec<String>> {
+    expand_tag_lists(resolve_tag_lists(tags).await).await
+}
+
+// Parent tags should also match works with any of their child tags
+pub async fn expand_tag_lists(mut tag_lists: Vec<Vec<String>>) -> Vec<Vec<String>> {
     let mongodb = MONGODB.get().unwrap();
-    let tags = query.tags.to_string().to_lowercase().split_whitespace().take(5).map(|tag| tag.to_string()).collect::<Vec<String>>();
-    let mut filter = None;
 
-    if !tags.is_empty() {
-        let mut tag_lists = vec![];
+    for tag_list in &mut tag_lists {
+        if let Ok(expanded_tags) = mongodb.tag_graph.expand(tag_list).await {
+            *tag_list = expanded_tags;
+        }
+    }
 
-        for tag in tags {
-            let mut normalized_tags = vec![];
+    tag_lists
+}
 
-            if let Some(pixiv_tags) = CONFIG.bookmark_tag_mappings.get(&tag) {
-                normalized_tags.extend(pixiv_tags.iter().map(|pixiv_tag| pixiv_tag.to_lowercase()));
-            }
+// Like `get_tag_lists`, but without the child tags
+pub async fn resolve_tag_lists(tags: &str) -> Vec<Vec<String>> {
+    let mongodb = MONGODB.get().unwrap();
+    let tags = tags.to_lowercase().split_whitespace().take(5).map(|tag| tag.to_string()).collect::<Vec<String>>();
+    let mut tag_lists = vec![];
 
-            for resolved_bookmark_tag in mongodb.bookmarks.tags.resolve_from_name_or_id(&tag).await.unwrap_or_default() {
-                normalized_tags.push(resolved_bookmark_tag.id);
-            }
+    for tag in tags {
+        let mut normalized_tags = vec![];
 
-            normalized_tags.push(tag);
+        if let Some(tag_mapping) = mongodb.tag_mappings.get(&tag).await.unwrap_or_default() {
+            normalized_tags.extend(tag_mapping.pixiv_tags.iter().map(|pixiv_tag| pixiv_tag.to_lowercase()));
+        }
 
-            tag_lists.push(normalized_tags);
+        for resolved_bookmark_tag in mongodb.bookmarks.tags.resolve_from_name_or_id(&tag).await.unwrap_or_default() {
+            normalized_tags.push(resolved_bookmark_tag.id);
         }
 
-        filter = Some(doc! { "$and": tag_lists.iter().map(|list| doc! { "tags": { "$in": list } }).collect::<Vec<Document>>() });
+        normalized_tags.push(tag);
+        tag_lists.push(normalized_tags);
     }
 
-    let count = match mongodb.bookmarks.count(filter.clone()).await {
-        Ok(count) => count,
-        Err(error) => return Json(Response::Error(format!("{error:?}"))),
-    };
+    tag_lists
+}
 
-    let bookmarks = match mongodb.bookmarks.find(filter, query.offset, query.limit, query.0.sort).await {
-        Ok(bookmarks) => bookmarks,
-        Err(error) => return Json(Response::Error(format!("{error:?}"))),
-    };
+// Bookmarks must have at least one tag from every list
+pub fn get_tag_filter(tag_lists: &[Vec<String>]) -> Option<Document> {
+    if tag_lists.is_empty() {
+        return None;
+    }
 
-    Json(Response::Data(PixivBookmarkPageBody { works: bookmarks, total: count }))
+    Some(doc! { "$and": tag_lists.iter().map(|list| doc! { "tags": { "$in": list } }).collect::<Vec<Document>>() })
 }
 
-#[derive(Deserialize)]
+// Bookmarks that are private on pixiv are only shown to tokens with the read:private scope
+pub fn get_visibility_filter(filter: Option<Document>, include_private: bool) -> Option<Document> {
+    if include_private {
+        return filter;
+    }
+
+    let public_filter = doc! { "bookmarkData.private": { "$ne": true } };
+
+    Some(match filter {
+        Some(filter) => doc! { "$and": [filter, public_filter] },
+        None => public_filter,
+    })
+}
+
+#[derive(Deserialize, IntoParams)]
 pub struct Pagination {
     #[serde(default)]
     tags: String,
 
+    // Kept for compatibility, but the cursors should be preferred as offsets can skip or repeat bookmarks when new ones are synced
     #[serde(default)]
     offset: u64,
 
+    #[serde(default)]
+    cursor: Option<String>,
+
     #[serde(default = "Pagination::default_limit")]
     limit: i64,
 
     #[serde(default = "Pagination::default_sort")]
     sort: PaginationSort,
+
+    #[serde(default)]
+    sort_by: PaginationSortBy,
+
+    // The seed for the random order, which is generated if not given
+    #[serde(default)]
+    seed: Option<u32>,
+
+    #[serde(default)]
+    lang: Option<TagLanguage>,
+
+    // A comma-separated list of facets to include
+    #[serde(default)]
+    facets: String,
 }
 
 impl Pagination {
@@ -68,9 +185,41 @@ impl Pagination {
     }
 }
 
-#[derive(Deserialize, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum PaginationSort {
     Ascending,
     Descending,
 }
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PaginationSortBy {
+    #[default]
+    SyncDate,
+    CreateDate,
+    UpdateDate,
+    Title,
+    Artist,
+    PageCount,
+    Resolution,
+    AspectRatio,
+    Random,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BookmarksPage {
+    pub works: Vec<PixivBookmarkPageBodyWork>,
+    pub total: u64,
+
+    // Cursors for the next and previous pages, which can be passed as `cursor`
+    pub next: Option<String>,
+    pub prev: Option<String>,
+
+    // The seed of the random order, which can be passed again to get the same order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facets: Option<BTreeMap<String, Vec<FacetBucket>>>,
+}
@@ -0,0 +1,68 @@
+use crate::{
+    MONGODB, RESPONSE_CACHE,
+    pixiv::{PixivBookmarkPageBodyWork, PixivBookmarks},
+    routes::{ApiError, DataResponse, Path, Response},
+};
+use axum::http::StatusCode;
+use tracing::{error, info};
+
+#[utoipa::path(
+    post,
+    path = "/api/bookmarks/{id}",
+    tag = "bookmarks",
+    params(("id" = u32, Path, description = "The artwork ID")),
+    responses((status = 200, body = inline(DataResponse<PixivBookmarkPageBodyWork>)), (status = "default", body = ApiError)),
+    security(("api_token" = [])),
+)]
+pub async fn handler(bookmark_id: Path<u32>) -> Response<PixivBookmarkPageBodyWork> {
+    let mongodb = MONGODB.get().unwrap();
+    let bookmark_id = bookmark_id.to_string();
+
+    match mongodb.bookmarks.get(&bookmark_id).await {
+        Ok(Some(bookmark)) => return Response::Data(bookmark),
+        Ok(None) => {},
+        Err(error) => {
+            error!("An error occurred while trying to get bookmark {bookmark_id}: {error:?}");
+            return Response::Error(error.into());
+        },
+    }
+
+    if let Err(error) = PixivBookmarks::add(&bookmark_id).await {
+        error!("An error occurred while trying to add bookmark {bookmark_id} on pixiv: {error:?}");
+        return Response::Error(error.into());
+    }
+
+    // pixiv doesn't return the bookmarked artwork, but new bookmarks are always at the top of the first page
+    let bookmarks = match PixivBookmarks::get_page(1, "").await {
+        Ok(bookmarks) => bookmarks,
+        Err(error) => {
+            error!("An error occurred while trying to get bookmark page 1: {error:?}");
+            return Response::Error(error.into());
+        },
+    };
+
+    let Some(bookmark) = bookmarks.body.works.into_iter().find(|bookmark| bookmark.id == bookmark_id) else {
+        return Response::Error(ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            "pixiv_error",
+            format!("Bookmark {bookmark_id} was added on pixiv but is not in the recent bookmarks."),
+        ));
+    };
+
+    if let Err(error) = mongodb.bookmarks.insert_many(vec![bookmark]).await {
+        error!("An error occurred while trying to insert bookmark {bookmark_id}: {error:?}");
+        return Response::Error(error.into());
+    }
+
+    info!("Bookmark {bookmark_id} was added on pixiv and inserted.");
+    RESPONSE_CACHE.invalidate();
+
+    match mongodb.bookmarks.get(&bookmark_id).await {
+        Ok(Some(bookmark)) => Response::Data(bookmark),
+        Ok(None) => Response::Error(ApiError::not_found(
+            "bookmark_not_found",
+            format!("Bookmark {bookmark_id} could not be found after inserting it."),
+        )),
+        Err(error) => Response::Error(error.into()),
+    }
+}
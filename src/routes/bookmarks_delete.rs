@@ -0,0 +1,56 @@
+use crate::{
+    MONGODB, RESPONSE_CACHE,
+    pixiv::{PixivBookmarks, PixivIllust},
+    routes::{ApiError, DataResponse, Path, Response},
+};
+use tracing::{error, info};
+
+#[utoipa::path(
+    delete,
+    path = "/api/bookmarks/{id}",
+    tag = "bookmarks",
+    params(("id" = u32, Path, description = "The artwork ID")),
+    responses((status = 200, body = inline(DataResponse<bool>)), (status = "default", body = ApiError)),
+    security(("api_token" = [])),
+)]
+pub async fn handler(bookmark_id: Path<u32>) -> Response<bool> {
+    let mongodb = MONGODB.get().unwrap();
+    let bookmark_id = bookmark_id.to_string();
+
+    let local_bookmark = match mongodb.bookmarks.get(&bookmark_id).await {
+        Ok(bookmark) => bookmark,
+        Err(error) => {
+            error!("An error occurred while trying to get bookmark {bookmark_id}: {error:?}");
+            return Response::Error(error.into());
+        },
+    };
+
+    // pixiv deletes bookmarks by their own bookmark ID, which we only have if the bookmark was synced
+    let bookmark_data = match local_bookmark.and_then(|bookmark| bookmark.bookmark_data) {
+        Some(bookmark_data) => Some(bookmark_data),
+        None => match PixivIllust::get(&bookmark_id).await {
+            Ok(illust) => illust.body.bookmark_data,
+            Err(error) => {
+                error!("An error occurred while trying to get artwork {bookmark_id} from pixiv: {error:?}");
+                return Response::Error(error.into());
+            },
+        },
+    };
+
+    if let Some(bookmark_data) = bookmark_data
+        && let Err(error) = PixivBookmarks::delete(&bookmark_data.id).await
+    {
+        error!("An error occurred while trying to delete bookmark {bookmark_id} on pixiv: {error:?}");
+        return Response::Error(error.into());
+    }
+
+    if let Err(error) = mongodb.bookmarks.delete(&bookmark_id).await {
+        error!("An error occurred while trying to delete bookmark {bookmark_id}: {error:?}");
+        return Response::Error(error.into());
+    }
+
+    info!("Bookmark {bookmark_id} was deleted on pixiv and removed from the local database.");
+    RESPONSE_CACHE.invalidate();
+
+    Response::Data(true)
+}
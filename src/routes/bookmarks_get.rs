@@ -0,0 +1,42 @@
+use crate::{
+    MONGODB,
+    auth::Scopes,
+    mongodb::{ApiTokenScope, TagLanguage},
+    pixiv::PixivBookmarkPageBodyWork,
+    routes::{ApiError, DataResponse, Path, Query, Response},
+};
+use serde::Deserialize;
+use tracing::error;
+use utoipa::IntoParams;
+
+#[utoipa::path(
+    get,
+    path = "/api/bookmarks/{id}",
+    tag = "bookmarks",
+    params(("id" = u32, Path, description = "The artwork ID"), BookmarkQuery),
+    responses((status = 200, body = inline(DataResponse<PixivBookmarkPageBodyWork>)), (status = "default", body = ApiError)),
+    security((), ("api_token" = [])),
+)]
+pub async fn handler(scopes: Scopes, bookmark_id: Path<u32>, query: Query<BookmarkQuery>) -> Response<PixivBookmarkPageBodyWork> {
+    let mongodb = MONGODB.get().unwrap();
+    let bookmark_id = *bookmark_id;
+
+    match mongodb.bookmarks.get_translated(bookmark_id, query.lang).await {
+        // Private bookmarks are hidden as if they weren't mirrored
+        Ok(Some(bookmark)) if !bookmark.is_private() || scopes.has(ApiTokenScope::ReadPrivate) => Response::Data(bookmark),
+        Ok(_) => Response::Error(ApiError::not_found(
+            "bookmark_not_found",
+            format!("Bookmark {bookmark_id} does not exist in the local database."),
+        )),
+        Err(error) => {
+            error!("An error occurred while trying to get bookmark {bookmark_id}: {error:?}");
+            Response::Error(error.into())
+        },
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct BookmarkQuery {
+    #[serde(default)]
+    pub lang: Option<TagLanguage>,
+}
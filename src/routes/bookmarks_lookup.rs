@@ -0,0 +1,68 @@
+use crate::{
+    MONGODB,
+    auth::Scopes,
+    mongodb::{ApiTokenScope, TagLanguage},
+    pixiv::{PIXIV_BOOKMARKS_PER_PAGE, PixivBookmarkPageBodyWork},
+    routes::{ApiError, DataResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use utoipa::ToSchema;
+
+#[utoipa::path(
+    post,
+    path = "/api/bookmarks/lookup",
+    tag = "bookmarks",
+    request_body = BookmarksLookupBody,
+    responses((status = 200, body = inline(DataResponse<BookmarksLookup>)), (status = "default", body = ApiError)),
+    security((), ("api_token" = [])),
+)]
+pub async fn handler(scopes: Scopes, body: Json<BookmarksLookupBody>) -> Response<BookmarksLookup> {
+    match get_bookmarks_lookup(&body, scopes.has(ApiTokenScope::ReadPrivate)).await {
+        Ok(lookup) => Response::Data(lookup),
+        Err(error) => Response::Error(error),
+    }
+}
+
+pub async fn get_bookmarks_lookup(body: &BookmarksLookupBody, include_private: bool) -> Result<BookmarksLookup, ApiError> {
+    let mongodb = MONGODB.get().unwrap();
+
+    if body.ids.len() > PIXIV_BOOKMARKS_PER_PAGE as usize {
+        return Err(ApiError::bad_request(
+            "too_many_ids",
+            format!("Only up to {PIXIV_BOOKMARKS_PER_PAGE} bookmarks can be looked up at once."),
+        ));
+    }
+
+    let mut works = match mongodb.bookmarks.lookup(&body.ids, body.lang).await {
+        Ok(works) => works,
+        Err(error) => {
+            error!("An error occurred while trying to look up bookmarks: {error:?}");
+            return Err(error.into());
+        },
+    };
+
+    // Private bookmarks are reported as missing
+    works.retain(|work| include_private || !work.is_private());
+
+    let (mirrored, missing) = body.ids.iter().cloned().partition(|id| works.iter().any(|work| &work.id == id));
+
+    Ok(BookmarksLookup { mirrored, missing, works })
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct BookmarksLookupBody {
+    ids: Vec<String>,
+
+    #[serde(default)]
+    lang: Option<TagLanguage>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BookmarksLookup {
+    // The given IDs that are in the local database, and the ones that aren't
+    pub mirrored: Vec<String>,
+    pub missing: Vec<String>,
+
+    pub works: Vec<PixivBookmarkPageBodyWork>,
+}
@@ -0,0 +1,80 @@
+use crate::{
+    MONGODB, RESPONSE_CACHE,
+    pixiv::PixivBookmarks,
+    routes::{ApiError, DataResponse, Json, Path, Response},
+};
+use serde::Deserialize;
+use tracing::error;
+use utoipa::ToSchema;
+
+#[utoipa::path(
+    patch,
+    path = "/api/bookmarks/{id}/user-tags",
+    tag = "bookmarks",
+    params(("id" = u32, Path, description = "The artwork ID")),
+    request_body = UserTagsChange,
+    responses((status = 200, body = inline(DataResponse<Vec<String>>)), (status = "default", body = ApiError)),
+    security(("api_token" = [])),
+)]
+pub async fn handler(bookmark_id: Path<u32>, body: Json<UserTagsChange>) -> Response<Vec<String>> {
+    let mongodb = MONGODB.get().unwrap();
+    let bookmark_id = bookmark_id.to_string();
+
+    let bookmark = match mongodb.bookmarks.get(&bookmark_id).await {
+        Ok(Some(bookmark)) => bookmark,
+        Ok(None) => {
+            return Response::Error(ApiError::not_found(
+                "bookmark_not_found",
+                format!("Bookmark {bookmark_id} does not exist in the local database."),
+            ));
+        },
+        Err(error) => {
+            error!("An error occurred while trying to get bookmark {bookmark_id}: {error:?}");
+            return Response::Error(error.into());
+        },
+    };
+
+    let Some(bookmark_data) = bookmark.bookmark_data else {
+        return Response::Error(ApiError::not_found("bookmark_data_not_found", format!("Bookmark {bookmark_id} has no bookmark data.")));
+    };
+
+    if !body.add.is_empty() {
+        if let Err(error) = PixivBookmarks::add_user_tags(&[&bookmark_data.id], &body.add).await {
+            error!("An error occurred while trying to add user tags to bookmark {bookmark_id} on pixiv: {error:?}");
+            return Response::Error(error.into());
+        }
+
+        if let Err(error) = mongodb.bookmarks.add_user_tags(&[&bookmark_id], &body.add).await {
+            error!("An error occurred while trying to add user tags to bookmark {bookmark_id}: {error:?}");
+            return Response::Error(error.into());
+        }
+    }
+
+    if !body.remove.is_empty() {
+        if let Err(error) = PixivBookmarks::remove_user_tags(&[&bookmark_data.id], &body.remove).await {
+            error!("An error occurred while trying to remove user tags from bookmark {bookmark_id} on pixiv: {error:?}");
+            return Response::Error(error.into());
+        }
+
+        if let Err(error) = mongodb.bookmarks.remove_user_tags(&[&bookmark_id], &body.remove).await {
+            error!("An error occurred while trying to remove user tags from bookmark {bookmark_id}: {error:?}");
+            return Response::Error(error.into());
+        }
+    }
+
+    RESPONSE_CACHE.invalidate();
+
+    match mongodb.bookmarks.get(&bookmark_id).await {
+        Ok(bookmark) => Response::Data(bookmark.map(|bookmark| bookmark.user_tags).unwrap_or_default()),
+        Err(error) => Response::Error(error.into()),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UserTagsChange {
+    #[serde(default)]
+    add: Vec<String>,
+
+    #[serde(default)]
+    remove: Vec<String>,
+}
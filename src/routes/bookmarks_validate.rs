@@ -1,37 +1,40 @@
-use crate::{MONGODB, REQWEST, routes::Response};
-use axum::{Json, extract::Path};
-use reqwest::StatusCode;
-use tracing::{error, info};
+use crate::{
+    MONGODB,
+    pixiv::PixivArtworkState,
+    routes::{ApiError, DataResponse, Path, Response},
+    sync::validate_bookmark,
+};
+use tracing::error;
 
-pub async fn handler(bookmark_id: Path<u32>) -> Json<Response<bool>> {
+#[utoipa::path(
+    get,
+    path = "/api/bookmarks/{id}/validate",
+    tag = "bookmarks",
+    params(("id" = u32, Path, description = "The artwork ID")),
+    responses((status = 200, body = inline(DataResponse<bool>)), (status = "default", body = ApiError)),
+    security(("api_token" = [])),
+)]
+pub async fn handler(bookmark_id: Path<u32>) -> Response<bool> {
     let mongodb = MONGODB.get().unwrap();
-    let bookmark_id = *bookmark_id;
+    let bookmark_id = bookmark_id.to_string();
 
-    match mongodb.bookmarks.get(bookmark_id).await {
+    match mongodb.bookmarks.get(&bookmark_id).await {
         Ok(bookmark) => {
             if bookmark.is_none() {
-                return Json(Response::Data(false));
+                return Response::Data(false);
             }
         },
         Err(error) => {
             error!("An error occurred while trying to get bookmark {bookmark_id}: {error:?}");
-            return Json(Response::Error(format!("{error:?}")));
+            return Response::Error(error.into());
         },
     }
 
-    match REQWEST.get(format!("https://pixiv.net/artworks/{bookmark_id}")).send().await {
-        Ok(res) => {
-            if res.status() == StatusCode::NOT_FOUND {
-                info!("Bookmark {bookmark_id} exists in the local database but was not found on pixiv. Deleting...");
-                _ = mongodb.bookmarks.delete(bookmark_id).await;
-                return Json(Response::Data(false));
-            }
-        },
+    match validate_bookmark(&bookmark_id).await {
+        Ok(state) => Response::Data(state != PixivArtworkState::Deleted),
         Err(error) => {
-            error!("An error occurred while trying to get bookmark {bookmark_id} from pixiv: {error:?}");
-            return Json(Response::Error(format!("{error:?}")));
+            error!("An error occurred while trying to validate bookmark {bookmark_id}: {error:?}");
+            Response::Error(error.into())
         },
     }
-
-    Json(Response::Data(true))
 }
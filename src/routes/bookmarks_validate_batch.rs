@@ -0,0 +1,116 @@
+use crate::{
+    CONFIG, MONGODB,
+    pixiv::PixivArtworkState,
+    rate_limit::RateLimitBudget,
+    routes::{ApiError, DataResponse, Json, Response},
+    sync::validate_bookmark,
+};
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, time::Duration};
+use tokio::time::{Instant, sleep_until, timeout_at};
+use tracing::error;
+use utoipa::ToSchema;
+
+// Every artwork is a separate request to pixiv
+const VALIDATE_BOOKMARKS_BATCH_LIMIT: usize = 50;
+const VALIDATE_BOOKMARK_COOLDOWN: Duration = Duration::from_millis(500);
+
+// How long before the request timeout the batch stops, so the states that were already fetched can still be returned
+const VALIDATE_BOOKMARKS_TIMEOUT_MARGIN: Duration = Duration::from_secs(2);
+
+// Returns the state of each given bookmark, or null if it's not in the local database
+// Bookmarks that couldn't be validated get an error state instead, so the rest of the batch isn't lost
+// Every validated bookmark costs a request of the pixiv rate limit, and the request itself pays for the first one
+#[utoipa::path(
+    post,
+    path = "/api/bookmarks/validate",
+    tag = "bookmarks",
+    request_body = BookmarksValidateBody,
+    responses(
+        (status = 200, body = inline(DataResponse<BTreeMap<String, Option<BookmarkValidation>>>)),
+        (status = "default", body = ApiError),
+    ),
+    security(("api_token" = [])),
+)]
+pub async fn handler(budget: RateLimitBudget, body: Json<BookmarksValidateBody>) -> Response<BTreeMap<String, Option<BookmarkValidation>>> {
+    let mongodb = MONGODB.get().unwrap();
+
+    if body.ids.len() > VALIDATE_BOOKMARKS_BATCH_LIMIT {
+        return Response::Error(ApiError::bad_request(
+            "too_many_ids",
+            format!("Only up to {VALIDATE_BOOKMARKS_BATCH_LIMIT} bookmarks can be validated at once."),
+        ));
+    }
+
+    let mirrored = match mongodb.bookmarks.find_raw(doc! { "_id": { "$in": &body.ids } }).await {
+        Ok(bookmarks) => bookmarks.into_iter().map(|bookmark| bookmark.id).collect::<Vec<String>>(),
+        Err(error) => {
+            error!("An error occurred while trying to get bookmarks: {error:?}");
+            return Response::Error(error.into());
+        },
+    };
+
+    let request_timeout = Duration::from_secs(CONFIG.load().server.request_timeout_seconds);
+    let deadline = Instant::now() + request_timeout.saturating_sub(VALIDATE_BOOKMARKS_TIMEOUT_MARGIN);
+    let mut states = BTreeMap::new();
+    let mut attempted = 0;
+
+    for id in &body.ids {
+        if !mirrored.contains(id) {
+            states.insert(id.clone(), None);
+            continue;
+        }
+
+        if Instant::now() >= deadline {
+            states.insert(id.clone(), Some(BookmarkValidation::Error(BookmarkValidationError::Skipped)));
+            continue;
+        }
+
+        if attempted != 0 && budget.take().is_err() {
+            states.insert(id.clone(), Some(BookmarkValidation::Error(BookmarkValidationError::RateLimited)));
+            continue;
+        }
+
+        let validation = match timeout_at(deadline, validate_bookmark(id)).await {
+            Ok(Ok(state)) => BookmarkValidation::State(state),
+            Ok(Err(error)) => {
+                error!("An error occurred while trying to validate bookmark {id}: {error:?}");
+                BookmarkValidation::Error(BookmarkValidationError::Failed)
+            },
+            Err(_) => BookmarkValidation::Error(BookmarkValidationError::Skipped),
+        };
+
+        states.insert(id.clone(), Some(validation));
+        attempted += 1;
+
+        sleep_until(deadline.min(Instant::now() + VALIDATE_BOOKMARK_COOLDOWN)).await;
+    }
+
+    Response::Data(states)
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct BookmarksValidateBody {
+    ids: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum BookmarkValidation {
+    State(PixivArtworkState),
+    Error(BookmarkValidationError),
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BookmarkValidationError {
+    // pixiv couldn't be reached or returned an error
+    Failed,
+
+    // The client ran out of pixiv requests before getting to this bookmark
+    RateLimited,
+
+    // The batch ran out of time before getting to this bookmark, so it should be validated again later
+    Skipped,
+}
@@ -0,0 +1,20 @@
+use axum::response::Html;
+
+// The API reference is loaded from a CDN, so nothing has to be bundled
+const DOCS_HTML: &str = r#"<!doctype html>
+<html>
+    <head>
+        <title>flazxiv API</title>
+        <meta charset="utf-8" />
+        <meta name="viewport" content="width=device-width, initial-scale=1" />
+    </head>
+    <body>
+        <script id="api-reference" data-url="/api/openapi.json"></script>
+        <script src="https://cdn.jsdelivr.net/npm/@scalar/api-reference"></script>
+    </body>
+</html>
+"#;
+
+pub async fn handler() -> Html<&'static str> {
+    Html(DOCS_HTML)
+}
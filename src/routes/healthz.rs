@@ -0,0 +1,7 @@
+use axum::Json;
+use serde_json::{Value, json};
+
+// The process is alive if it can respond at all
+pub async fn handler() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
@@ -0,0 +1,29 @@
+use crate::{METRICS, MONGODB, mongodb::TagTranslationJobStatus};
+use axum::{
+    http::{StatusCode, header::CONTENT_TYPE},
+    response::IntoResponse,
+};
+use prometheus::{Encoder, TextEncoder};
+use tracing::error;
+
+pub async fn handler() -> impl IntoResponse {
+    let mongodb = MONGODB.get().unwrap();
+
+    // The queue depth is read from the database when scraped instead of being tracked
+    for (status, label) in [(TagTranslationJobStatus::Pending, "pending"), (TagTranslationJobStatus::Failed, "failed")] {
+        match mongodb.bookmarks.tags.queue.count(status).await {
+            Ok(count) => METRICS.tag_translation_queue_jobs.with_label_values(&[label]).set(count as i64),
+            Err(error) => error!("An error occurred while trying to count {label} tag translation jobs: {error:?}"),
+        }
+    }
+
+    let encoder = TextEncoder::new();
+    let mut body = vec![];
+
+    if let Err(error) = encoder.encode(&METRICS.registry.gather(), &mut body) {
+        error!("An error occurred while trying to encode metrics: {error:?}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    ([(CONTENT_TYPE, encoder.format_type().to_string())], body).into_response()
+}
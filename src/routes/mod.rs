@@ -1,12 +1,203 @@
+pub mod admin_bookmark_tags_history;
+pub mod admin_bookmark_tags_locked;
+pub mod admin_bookmark_tags_name;
+pub mod admin_bookmark_tags_retranslate;
+pub mod admin_tag_queue;
+pub mod admin_user_tag_rules;
 pub mod bookmark_tags;
+pub mod bookmark_tags_autocomplete;
+pub mod bookmark_tags_co_occurrences;
+pub mod bookmark_tags_related;
 pub mod bookmarks;
+pub mod bookmarks_add;
+pub mod bookmarks_delete;
+pub mod bookmarks_get;
+pub mod bookmarks_lookup;
+pub mod bookmarks_user_tags;
 pub mod bookmarks_validate;
+pub mod bookmarks_validate_batch;
+pub mod docs;
+pub mod healthz;
+pub mod metrics;
+pub mod openapi;
+pub mod readyz;
+pub mod tag_mappings;
+pub mod tag_mappings_delete;
+pub mod tag_mappings_get;
+pub mod tag_mappings_put;
+pub mod v1;
+pub mod v1_bookmarks;
+pub mod v1_bookmarks_get;
+pub mod v1_bookmarks_lookup;
 
-use serde::Serialize;
+use crate::pixiv::PixivError;
+use axum::{
+    extract::{
+        FromRequest, FromRequestParts,
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+    },
+    http::StatusCode,
+    response::IntoResponse,
+};
+use mongodb::error::ErrorKind;
+use serde::{Serialize, Serializer};
+use std::{fmt::Display, ops::Deref};
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
-#[serde(rename_all = "lowercase")]
+// Serialized as `{ "data": ... }` or `{ "error": "...", "code": "..." }`, with the status code of the error
+// The document describes the former as `DataResponse` and the latter as `ApiError`
 pub enum Response<T> {
     Data(T),
-    Error(String),
+
+    // Like `Data`, but for work that continues in the background after responding
+    Accepted(T),
+
+    Error(ApiError),
+}
+
+impl<T: Serialize> Serialize for Response<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Data(data) | Self::Accepted(data) => DataResponse { data }.serialize(serializer),
+            Self::Error(error) => error.serialize(serializer),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DataResponse<T> {
+    data: T,
+}
+
+impl<T: Serialize> IntoResponse for Response<T> {
+    fn into_response(self) -> axum::response::Response {
+        let status = match &self {
+            Self::Data(_) => StatusCode::OK,
+            Self::Accepted(_) => StatusCode::ACCEPTED,
+            Self::Error(error) => error.status,
+        };
+
+        (status, axum::Json(self)).into_response()
+    }
+}
+
+// The code is stable and meant for clients to match on, unlike the message
+#[derive(Serialize, ToSchema, Debug)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+
+    #[serde(rename = "error")]
+    message: String,
+
+    code: &'static str,
+}
+
+impl ApiError {
+    pub fn new<T: Display>(status: StatusCode, code: &'static str, message: T) -> Self {
+        Self { status, message: message.to_string(), code }
+    }
+
+    pub fn bad_request<T: Display>(code: &'static str, message: T) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    pub fn not_found<T: Display>(code: &'static str, message: T) -> Self {
+        Self::new(StatusCode::NOT_FOUND, code, message)
+    }
+}
+
+// Errors are classified by their cause, and the details are left to the logs instead of being returned
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        for cause in error.chain() {
+            // Only failing to reach the database means it is unavailable, other errors (like failed commands) are internal errors
+            if let Some(mongodb_error) = cause.downcast_ref::<mongodb::error::Error>()
+                && matches!(
+                    *mongodb_error.kind,
+                    ErrorKind::Io(_)
+                        | ErrorKind::ConnectionPoolCleared { .. }
+                        | ErrorKind::ServerSelection { .. }
+                        | ErrorKind::DnsResolve { .. }
+                )
+            {
+                return Self::new(StatusCode::SERVICE_UNAVAILABLE, "database_unavailable", "The database is unavailable.");
+            }
+
+            if let Some(pixiv_error) = cause.downcast_ref::<PixivError>() {
+                return Self::new(StatusCode::BAD_GATEWAY, "pixiv_error", pixiv_error);
+            }
+
+            if cause.is::<reqwest::Error>() {
+                return Self::new(StatusCode::BAD_GATEWAY, "pixiv_unavailable", "pixiv could not be reached.");
+            }
+        }
+
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "An internal error occurred.")
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::bad_request("invalid_query", rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::bad_request("invalid_path", rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(rejection.status(), "invalid_body", rejection.body_text())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        Response::<()>::Error(self).into_response()
+    }
+}
+
+// These are axum's extractors, but rejections are returned as API errors
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub struct Query<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub struct Path<T>(pub T);
+
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct Json<T>(pub T);
+
+impl<T> Deref for Query<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> Deref for Path<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> Deref for Json<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+pub async fn not_found() -> ApiError {
+    ApiError::not_found("route_not_found", "This route does not exist.")
 }
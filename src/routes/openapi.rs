@@ -0,0 +1,70 @@
+use crate::{
+    mongodb::TagLanguage,
+    routes::{
+        admin_bookmark_tags_history, admin_bookmark_tags_locked, admin_bookmark_tags_name, admin_bookmark_tags_retranslate,
+        admin_tag_queue, admin_user_tag_rules, bookmark_tags, bookmark_tags_autocomplete, bookmark_tags_co_occurrences,
+        bookmark_tags_related, bookmarks,
+        bookmarks::{PaginationSort, PaginationSortBy},
+        bookmarks_add, bookmarks_delete, bookmarks_get, bookmarks_lookup, bookmarks_user_tags, bookmarks_validate,
+        bookmarks_validate_batch, tag_mappings, tag_mappings_delete, tag_mappings_get, tag_mappings_put, v1_bookmarks, v1_bookmarks_get,
+        v1_bookmarks_lookup,
+    },
+};
+use axum::Json;
+use utoipa::{
+    Modify, OpenApi,
+    openapi::{
+        self,
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    },
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "flazxiv", description = "A pixiv bookmark API."),
+    paths(
+        admin_bookmark_tags_history::handler,
+        admin_bookmark_tags_locked::handler,
+        admin_bookmark_tags_name::handler,
+        admin_bookmark_tags_retranslate::handler,
+        admin_tag_queue::handler,
+        admin_user_tag_rules::handler,
+        bookmark_tags::handler,
+        bookmark_tags_autocomplete::handler,
+        bookmark_tags_co_occurrences::handler,
+        bookmark_tags_related::handler,
+        bookmarks::handler,
+        bookmarks_add::handler,
+        bookmarks_delete::handler,
+        bookmarks_get::handler,
+        bookmarks_lookup::handler,
+        bookmarks_user_tags::handler,
+        bookmarks_validate::handler,
+        bookmarks_validate_batch::handler,
+        tag_mappings::handler,
+        tag_mappings_delete::handler,
+        tag_mappings_get::handler,
+        tag_mappings_put::handler,
+        v1_bookmarks::handler,
+        v1_bookmarks_get::handler,
+        v1_bookmarks_lookup::handler,
+    ),
+    // Schemas that are only used by query parameters aren't collected automatically
+    components(schemas(PaginationSort, PaginationSortBy, TagLanguage)),
+    modifiers(&ApiTokenSecurity),
+)]
+pub struct ApiDoc;
+
+// Routes that change anything require the API token as a bearer token
+struct ApiTokenSecurity;
+
+impl Modify for ApiTokenSecurity {
+    fn modify(&self, openapi: &mut openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_default();
+        components.add_security_scheme("api_token", SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()));
+    }
+}
+
+pub async fn handler() -> Json<openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
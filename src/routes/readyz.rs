@@ -0,0 +1,9 @@
+use crate::{HEALTH, health::Readiness};
+use axum::{Json, http::StatusCode};
+
+pub async fn handler() -> (StatusCode, Json<Readiness>) {
+    let readiness = HEALTH.readiness().await;
+    let status = if readiness.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    (status, Json(readiness))
+}
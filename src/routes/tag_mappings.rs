@@ -0,0 +1,25 @@
+use crate::{
+    MONGODB,
+    mongodb::TagMapping,
+    routes::{ApiError, DataResponse, Response},
+};
+use tracing::error;
+
+#[utoipa::path(
+    get,
+    path = "/api/tag-mappings",
+    tag = "tag mappings",
+    responses((status = 200, body = inline(DataResponse<Vec<TagMapping>>)), (status = "default", body = ApiError)),
+    security((), ("api_token" = [])),
+)]
+pub async fn handler() -> Response<Vec<TagMapping>> {
+    let mongodb = MONGODB.get().unwrap();
+
+    match mongodb.tag_mappings.find().await {
+        Ok(tag_mappings) => Response::Data(tag_mappings),
+        Err(error) => {
+            error!("An error occurred while trying to get tag mappings: {error:?}");
+            Response::Error(error.into())
+        },
+    }
+}
@@ -0,0 +1,32 @@
+use crate::{
+    MONGODB, RESPONSE_CACHE,
+    routes::{ApiError, DataResponse, Path, Response},
+};
+use tracing::{error, info};
+
+#[utoipa::path(
+    delete,
+    path = "/api/tag-mappings/{name}",
+    tag = "tag mappings",
+    params(("name" = String, Path, description = "The tag mapping name")),
+    responses((status = 200, body = inline(DataResponse<bool>)), (status = "default", body = ApiError)),
+    security(("api_token" = [])),
+)]
+pub async fn handler(name: Path<String>) -> Response<bool> {
+    let mongodb = MONGODB.get().unwrap();
+
+    match mongodb.tag_mappings.delete(&*name).await {
+        Ok(deleted) => {
+            if deleted {
+                info!(r#"Tag mapping "{}" was deleted."#, *name);
+                RESPONSE_CACHE.invalidate();
+            }
+
+            Response::Data(deleted)
+        },
+        Err(error) => {
+            error!(r#"An error occurred while trying to delete tag mapping "{}": {error:?}"#, *name);
+            Response::Error(error.into())
+        },
+    }
+}
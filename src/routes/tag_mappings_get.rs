@@ -0,0 +1,27 @@
+use crate::{
+    MONGODB,
+    mongodb::TagMapping,
+    routes::{ApiError, DataResponse, Path, Response},
+};
+use tracing::error;
+
+#[utoipa::path(
+    get,
+    path = "/api/tag-mappings/{name}",
+    tag = "tag mappings",
+    params(("name" = String, Path, description = "The tag mapping name")),
+    responses((status = 200, body = inline(DataResponse<TagMapping>)), (status = "default", body = ApiError)),
+    security((), ("api_token" = [])),
+)]
+pub async fn handler(name: Path<String>) -> Response<TagMapping> {
+    let mongodb = MONGODB.get().unwrap();
+
+    match mongodb.tag_mappings.get(&*name).await {
+        Ok(Some(tag_mapping)) => Response::Data(tag_mapping),
+        Ok(None) => Response::Error(ApiError::not_found("tag_mapping_not_found", format!(r#"Tag mapping "{}" does not exist."#, *name))),
+        Err(error) => {
+            error!(r#"An error occurred while trying to get tag mapping "{}": {error:?}"#, *name);
+            Response::Error(error.into())
+        },
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{
+    MONGODB, RESPONSE_CACHE,
+    mongodb::TagMapping,
+    routes::{ApiError, DataResponse, Json, Path, Response},
+};
+use serde::Deserialize;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+#[utoipa::path(
+    put,
+    path = "/api/tag-mappings/{name}",
+    tag = "tag mappings",
+    params(("name" = String, Path, description = "The tag mapping name")),
+    request_body = TagMappingBody,
+    responses((status = 200, body = inline(DataResponse<TagMapping>)), (status = "default", body = ApiError)),
+    security(("api_token" = [])),
+)]
+pub async fn handler(name: Path<String>, body: Json<TagMappingBody>) -> Response<TagMapping> {
+    let mongodb = MONGODB.get().unwrap();
+    let name = name.trim().to_lowercase();
+    let pixiv_tags = body
+        .pixiv_tags
+        .iter()
+        .map(|pixiv_tag| pixiv_tag.trim().to_string())
+        .filter(|pixiv_tag| !pixiv_tag.is_empty())
+        .collect::<Vec<String>>();
+
+    // Names are used as search terms, which are split by whitespace
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Response::Error(ApiError::bad_request("invalid_name", "Tag mapping names must not be empty or contain whitespace."));
+    }
+
+    if pixiv_tags.is_empty() {
+        return Response::Error(ApiError::bad_request("invalid_tag_mapping", "Tag mappings must have at least one pixiv tag."));
+    }
+
+    if let Err(error) = mongodb.tag_mappings.set(&name, &pixiv_tags).await {
+        error!(r#"An error occurred while trying to set tag mapping "{name}": {error:?}"#);
+        return Response::Error(error.into());
+    }
+
+    info!(r#"Tag mapping "{name}" was set to {pixiv_tags:?}."#);
+    RESPONSE_CACHE.invalidate();
+
+    Response::Data(TagMapping { name, pixiv_tags })
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TagMappingBody {
+    pixiv_tags: Vec<String>,
+}
@@ -0,0 +1,172 @@
+use crate::{
+    pixiv::{PixivArtworkState, PixivBookmarkPageBodyWork},
+    routes::ApiError,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::sync::LazyLock;
+use utoipa::{IntoParams, ToSchema};
+
+// The fields of `WorkV1` as they are serialized, which `fields` can select from
+static WORK_V1_FIELDS: LazyLock<Vec<String>> = LazyLock::new(|| match serde_json::to_value(WorkV1::default()) {
+    Ok(Value::Object(object)) => object.into_iter().map(|(key, _)| key).collect(),
+    _ => panic!("WorkV1 must be serialized as an object"),
+});
+
+// A work as returned by the v1 API, which is separate from the stored bookmark so the storage can change without breaking the API
+#[derive(Serialize, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkV1 {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub alt: String,
+    pub url: String,
+    pub tags: Vec<String>,
+    pub user_tags: Vec<String>,
+    pub artist: ArtistV1,
+    pub illust_type: u64,
+    pub x_restrict: u64,
+    pub ai_type: u64,
+    pub width: u64,
+    pub height: u64,
+    pub page_count: u64,
+    pub create_date: String,
+    pub update_date: String,
+
+    // pixiv doesn't provide the bookmark date, so this is when the bookmark was synced
+    pub bookmarked_at: Option<String>,
+
+    pub private: bool,
+    pub masked: bool,
+    pub validation_state: Option<ValidationStateV1>,
+    pub last_validated_at: Option<String>,
+}
+
+impl From<PixivBookmarkPageBodyWork> for WorkV1 {
+    fn from(work: PixivBookmarkPageBodyWork) -> Self {
+        Self {
+            id: work.id,
+            title: work.title,
+            description: work.description,
+            alt: work.alt,
+            url: work.url,
+            tags: work.tags,
+            user_tags: work.user_tags,
+            artist: ArtistV1 { id: work.user_id, name: work.user_name },
+            illust_type: work.illust_type,
+            x_restrict: work.x_restrict,
+            ai_type: work.ai_type,
+            width: work.width,
+            height: work.height,
+            page_count: work.page_count,
+            create_date: work.create_date,
+            update_date: work.update_date,
+            bookmarked_at: work.sync_date,
+            private: work.bookmark_data.is_some_and(|bookmark_data| bookmark_data.private),
+            masked: work.is_masked,
+            validation_state: work.validation_state.map(ValidationStateV1::from),
+            last_validated_at: work.last_validated_at,
+        }
+    }
+}
+
+// Whether the artwork can still be viewed on pixiv, as of the last validation
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationStateV1 {
+    Available,
+    Deleted,
+    Private,
+    Masked,
+}
+
+impl From<PixivArtworkState> for ValidationStateV1 {
+    fn from(state: PixivArtworkState) -> Self {
+        match state {
+            PixivArtworkState::Available => Self::Available,
+            PixivArtworkState::Deleted => Self::Deleted,
+            PixivArtworkState::Private => Self::Private,
+            PixivArtworkState::Masked => Self::Masked,
+        }
+    }
+}
+
+#[derive(Serialize, Default, ToSchema)]
+pub struct ArtistV1 {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FieldsQuery {
+    // A comma-separated list of the work fields to include, or every field if empty
+    #[serde(default)]
+    fields: String,
+}
+
+impl FieldsQuery {
+    pub fn selection(&self) -> Result<FieldSelection, ApiError> {
+        let fields = self.fields.split(',').map(str::trim).filter(|field| !field.is_empty()).collect::<Vec<&str>>();
+
+        if let Some(field) = fields.iter().find(|field| !WORK_V1_FIELDS.iter().any(|allowed| allowed == *field)) {
+            return Err(ApiError::bad_request("unknown_field", format!(r#"Unknown field "{field}"."#)));
+        }
+
+        Ok(FieldSelection(if fields.is_empty() { None } else { Some(fields.into_iter().map(str::to_string).collect()) }))
+    }
+}
+
+pub struct FieldSelection(Option<Vec<String>>);
+
+impl FieldSelection {
+    // Converts the work into a v1 work with only the selected fields
+    pub fn apply(&self, work: PixivBookmarkPageBodyWork) -> Value {
+        let Ok(Value::Object(object)) = serde_json::to_value(WorkV1::from(work)) else { return Value::Null };
+
+        match &self.0 {
+            Some(fields) => Value::Object(object.into_iter().filter(|(key, _)| fields.contains(key)).collect::<Map<String, Value>>()),
+            None => Value::Object(object),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn work_v1_fields_match_the_serialized_keys() {
+        let fields = [
+            "id",
+            "title",
+            "description",
+            "alt",
+            "url",
+            "tags",
+            "userTags",
+            "artist",
+            "illustType",
+            "xRestrict",
+            "aiType",
+            "width",
+            "height",
+            "pageCount",
+            "createDate",
+            "updateDate",
+            "bookmarkedAt",
+            "private",
+            "masked",
+            "validationState",
+            "lastValidatedAt",
+        ];
+
+        let mut expected = fields.map(str::to_string).to_vec();
+        let mut actual = WORK_V1_FIELDS.clone();
+        expected.sort();
+        actual.sort();
+
+        assert_eq!(actual, expected);
+    }
+}
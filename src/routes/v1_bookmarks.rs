@@ -0,0 +1,56 @@
+use crate::{
+    auth::Scopes,
+    mongodb::{ApiTokenScope, FacetBucket},
+    routes::{
+        ApiError, DataResponse, Query, Response,
+        bookmarks::{Pagination, get_bookmarks_page},
+        v1::{FieldsQuery, WorkV1},
+    },
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/bookmarks",
+    tag = "v1",
+    params(Pagination, FieldsQuery),
+    responses((status = 200, body = inline(DataResponse<BookmarksPageV1>)), (status = "default", body = ApiError)),
+    security((), ("api_token" = [])),
+)]
+pub async fn handler(scopes: Scopes, query: Query<Pagination>, fields: Query<FieldsQuery>) -> Response<BookmarksPageV1> {
+    let selection = match fields.selection() {
+        Ok(selection) => selection,
+        Err(error) => return Response::Error(error),
+    };
+
+    match get_bookmarks_page(&query, scopes.has(ApiTokenScope::ReadPrivate)).await {
+        Ok(page) => Response::Data(BookmarksPageV1 {
+            works: page.works.into_iter().map(|work| selection.apply(work)).collect(),
+            total: page.total,
+            next: page.next,
+            prev: page.prev,
+            seed: page.seed,
+            facets: page.facets,
+        }),
+        Err(error) => Response::Error(error),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BookmarksPageV1 {
+    #[schema(value_type = Vec<WorkV1>)]
+    works: Vec<Value>,
+
+    total: u64,
+    next: Option<String>,
+    prev: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    facets: Option<BTreeMap<String, Vec<FacetBucket>>>,
+}
@@ -0,0 +1,43 @@
+use crate::{
+    MONGODB,
+    auth::Scopes,
+    mongodb::ApiTokenScope,
+    routes::{
+        ApiError, DataResponse, Path, Query, Response,
+        bookmarks_get::BookmarkQuery,
+        v1::{FieldsQuery, WorkV1},
+    },
+};
+use serde_json::Value;
+use tracing::error;
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/bookmarks/{id}",
+    tag = "v1",
+    params(("id" = u32, Path, description = "The artwork ID"), BookmarkQuery, FieldsQuery),
+    responses((status = 200, body = inline(DataResponse<WorkV1>)), (status = "default", body = ApiError)),
+    security((), ("api_token" = [])),
+)]
+pub async fn handler(scopes: Scopes, bookmark_id: Path<u32>, query: Query<BookmarkQuery>, fields: Query<FieldsQuery>) -> Response<Value> {
+    let mongodb = MONGODB.get().unwrap();
+    let bookmark_id = *bookmark_id;
+
+    let selection = match fields.selection() {
+        Ok(selection) => selection,
+        Err(error) => return Response::Error(error),
+    };
+
+    match mongodb.bookmarks.get_translated(bookmark_id, query.lang).await {
+        // Private bookmarks are hidden as if they weren't mirrored
+        Ok(Some(bookmark)) if !bookmark.is_private() || scopes.has(ApiTokenScope::ReadPrivate) => Response::Data(selection.apply(bookmark)),
+        Ok(_) => Response::Error(ApiError::not_found(
+            "bookmark_not_found",
+            format!("Bookmark {bookmark_id} does not exist in the local database."),
+        )),
+        Err(error) => {
+            error!("An error occurred while trying to get bookmark {bookmark_id}: {error:?}");
+            Response::Error(error.into())
+        },
+    }
+}
@@ -0,0 +1,46 @@
+use crate::{
+    auth::Scopes,
+    mongodb::ApiTokenScope,
+    routes::{
+        ApiError, DataResponse, Json, Query, Response,
+        bookmarks_lookup::{BookmarksLookupBody, get_bookmarks_lookup},
+        v1::{FieldsQuery, WorkV1},
+    },
+};
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/bookmarks/lookup",
+    tag = "v1",
+    params(FieldsQuery),
+    request_body = BookmarksLookupBody,
+    responses((status = 200, body = inline(DataResponse<BookmarksLookupV1>)), (status = "default", body = ApiError)),
+    security((), ("api_token" = [])),
+)]
+pub async fn handler(scopes: Scopes, fields: Query<FieldsQuery>, body: Json<BookmarksLookupBody>) -> Response<BookmarksLookupV1> {
+    let selection = match fields.selection() {
+        Ok(selection) => selection,
+        Err(error) => return Response::Error(error),
+    };
+
+    match get_bookmarks_lookup(&body, scopes.has(ApiTokenScope::ReadPrivate)).await {
+        Ok(lookup) => Response::Data(BookmarksLookupV1 {
+            mirrored: lookup.mirrored,
+            missing: lookup.missing,
+            works: lookup.works.into_iter().map(|work| selection.apply(work)).collect(),
+        }),
+        Err(error) => Response::Error(error),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BookmarksLookupV1 {
+    mirrored: Vec<String>,
+    missing: Vec<String>,
+
+    #[schema(value_type = Vec<WorkV1>)]
+    works: Vec<Value>,
+}
@@ -0,0 +1,157 @@
+use crate::{CONFIG, MONGODB, RESPONSE_CACHE, SHUTDOWN, pixiv::PixivBookmarks};
+use anyhow::Result;
+use mongodb::bson::{Document, doc};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, time::Duration};
+use tokio::time::sleep;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+// pixiv's bookmark tag editor allows up to 100 bookmarks at once
+const PIXIV_USER_TAG_BATCH_SIZE: usize = 100;
+const PUSH_USER_TAGS_COOLDOWN: Duration = Duration::from_millis(500);
+
+// A rule that adds a user tag to every bookmark matching all of its conditions
+#[derive(Deserialize, Clone, Debug)]
+pub struct UserTagRule {
+    pub user_tag: String,
+
+    // Uses the pixiv tags of a tag mapping as `any_tags`
+    #[serde(default)]
+    pub mapping: Option<String>,
+
+    #[serde(default)]
+    pub any_tags: Vec<String>,
+
+    #[serde(default)]
+    pub all_tags: Vec<String>,
+
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+
+    #[serde(default)]
+    pub exclude_ai_types: Vec<u64>,
+
+    #[serde(default)]
+    pub x_restricts: Option<Vec<u64>>,
+}
+
+impl UserTagRule {
+    // Returns the filter for bookmarks that match this rule but don't have the user tag yet, or None if the rule has no tag conditions
+    pub fn filter(&self, tag_mappings: &HashMap<String, Vec<String>>) -> Option<Document> {
+        let mut any_tags = self.any_tags.iter().map(|tag| tag.to_lowercase()).collect::<Vec<String>>();
+
+        if let Some(pixiv_tags) = self.mapping.as_ref().and_then(|mapping| tag_mappings.get(mapping)) {
+            any_tags.extend(pixiv_tags.iter().map(|pixiv_tag| pixiv_tag.to_lowercase()));
+        }
+
+        let all_tags = self.all_tags.iter().map(|tag| tag.to_lowercase()).collect::<Vec<String>>();
+        let exclude_tags = self.exclude_tags.iter().map(|tag| tag.to_lowercase()).collect::<Vec<String>>();
+
+        // A rule without any tag condition would tag every bookmark, which is most likely a mistake
+        if any_tags.is_empty() && all_tags.is_empty() {
+            return None;
+        }
+
+        let mut conditions = vec![doc! { "userTags": { "$ne": &self.user_tag } }];
+
+        if !any_tags.is_empty() {
+            conditions.push(doc! { "tags": { "$in": any_tags } });
+        }
+
+        if !all_tags.is_empty() {
+            conditions.push(doc! { "tags": { "$all": all_tags } });
+        }
+
+        if !exclude_tags.is_empty() {
+            conditions.push(doc! { "tags": { "$nin": exclude_tags } });
+        }
+
+        if !self.exclude_ai_types.is_empty() {
+            let exclude_ai_types = self.exclude_ai_types.iter().map(|ai_type| *ai_type as i64).collect::<Vec<i64>>();
+            conditions.push(doc! { "aiType": { "$nin": exclude_ai_types } });
+        }
+
+        if let Some(x_restricts) = &self.x_restricts {
+            let x_restricts = x_restricts.iter().map(|x_restrict| *x_restrict as i64).collect::<Vec<i64>>();
+            conditions.push(doc! { "xRestrict": { "$in": x_restricts } });
+        }
+
+        Some(doc! { "$and": conditions })
+    }
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct UserTagRuleResult {
+    pub user_tag: String,
+    pub ids: Vec<String>,
+}
+
+// Applies the configured user tag rules to the given bookmarks (or every bookmark if `ids` is None)
+// Nothing is written if `dry_run` is set. Otherwise, the user tags are added locally and also on pixiv if `push` is set
+pub async fn apply_user_tag_rules(ids: Option<&[String]>, dry_run: bool, push: bool) -> Result<Vec<UserTagRuleResult>> {
+    let mongodb = MONGODB.get().unwrap();
+    let config = CONFIG.load_full();
+    let tag_mappings = mongodb.tag_mappings.to_hashmap().await?;
+    let mut results = vec![];
+
+    for rule in &config.user_tag_rules {
+        if let Some(mapping) = &rule.mapping
+            && !tag_mappings.contains_key(mapping)
+        {
+            warn!(r#"User tag rule "{}" uses the unknown tag mapping "{mapping}"."#, rule.user_tag);
+        }
+
+        let Some(mut filter) = rule.filter(&tag_mappings) else { continue };
+
+        if let Some(ids) = ids {
+            filter = doc! { "$and": [filter, { "_id": { "$in": ids } }] };
+        }
+
+        let bookmarks = mongodb.bookmarks.find_raw(filter).await?;
+
+        if bookmarks.is_empty() {
+            continue;
+        }
+
+        let ids = bookmarks.iter().map(|bookmark| bookmark.id.clone()).collect::<Vec<String>>();
+
+        if !dry_run {
+            let user_tags = [rule.user_tag.clone()];
+
+            if push {
+                // Every chunk is added locally right after it's pushed, so stopping halfway leaves pixiv and the database in sync
+                for chunk in bookmarks.chunks(PIXIV_USER_TAG_BATCH_SIZE) {
+                    if SHUTDOWN.is_cancelled() {
+                        info!(r#"Stopped adding user tag "{}"."#, rule.user_tag);
+                        return Ok(results);
+                    }
+
+                    let bookmark_ids = chunk
+                        .iter()
+                        .filter_map(|bookmark| bookmark.bookmark_data.as_ref().map(|data| data.id.clone()))
+                        .collect::<Vec<String>>();
+
+                    if !bookmark_ids.is_empty() {
+                        PixivBookmarks::add_user_tags(&bookmark_ids, &user_tags).await?;
+                    }
+
+                    let chunk_ids = chunk.iter().map(|bookmark| bookmark.id.clone()).collect::<Vec<String>>();
+                    mongodb.bookmarks.add_user_tags(&chunk_ids, &user_tags).await?;
+                    RESPONSE_CACHE.invalidate();
+
+                    sleep(PUSH_USER_TAGS_COOLDOWN).await;
+                }
+            } else {
+                mongodb.bookmarks.add_user_tags(&ids, &user_tags).await?;
+                RESPONSE_CACHE.invalidate();
+            }
+
+            info!(r#"Added user tag "{}" to {} {}."#, rule.user_tag, ids.len(), if ids.len() == 1 { "bookmark" } else { "bookmarks" });
+        }
+
+        results.push(UserTagRuleResult { user_tag: rule.user_tag.clone(), ids });
+    }
+
+    Ok(results)
+}
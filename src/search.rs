@@ -0,0 +1,195 @@
+use crate::mongodb::BookmarkTagTranslations;
+use kakasi::{IsJapanese, convert, is_japanese};
+
+// Half-width katakana from U+FF66 to U+FF9D, in order
+const HALF_WIDTH_KATAKANA: &str =
+    "ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン";
+
+// Normalizes text for matching: full-width ASCII becomes half-width, half-width katakana becomes full-width, katakana becomes hiragana
+// Separators are removed so "genshin impact", "genshin_impact" and "GenshinImpact" are the same
+pub fn normalize(text: &str) -> String {
+    let mut normalized = String::new();
+
+    for char in text.chars() {
+        let code = char as u32;
+
+        let char = match code {
+            // Full-width ASCII
+            0xFF01..=0xFF5E => char::from_u32(code - 0xFEE0).unwrap_or(char),
+            // Ideographic space
+            0x3000 => ' ',
+            // Half-width katakana
+            0xFF66..=0xFF9D => HALF_WIDTH_KATAKANA.chars().nth((code - 0xFF66) as usize).unwrap_or(char),
+            // Half-width (han)dakuten, which modify the previous kana
+            0xFF9E | 0xFF9F => {
+                if let Some(voiced) = normalized.chars().last().and_then(|previous| add_dakuten(previous, code == 0xFF9F)) {
+                    normalized.pop();
+                    normalized.push(voiced);
+                }
+
+                continue;
+            },
+            _ => char,
+        };
+
+        // Katakana to hiragana (`ヴ` and the small `ヵ` and `ヶ` included)
+        let char = match char as u32 {
+            code @ 0x30A1..=0x30F6 => char::from_u32(code - 0x60).unwrap_or(char),
+            _ => char,
+        };
+
+        if char.is_whitespace() || matches!(char, '_' | '-' | '・') {
+            continue;
+        }
+
+        normalized.extend(char.to_lowercase());
+    }
+
+    normalized
+}
+
+// Returns the hiragana with a dakuten (or handakuten) from the given hiragana, if there's one
+fn add_dakuten(char: char, handakuten: bool) -> Option<char> {
+    let code = char as u32;
+
+    let voiced = match (char, handakuten) {
+        ('う', false) => 0x3094,
+        ('か'..='ぢ', false) if !code.is_multiple_of(2) => code + 1,
+        ('つ'..='ど', false) if code.is_multiple_of(2) => code + 1,
+        ('は'..='ぽ', false) if (code - 0x306F).is_multiple_of(3) => code + 1,
+        ('は'..='ぽ', true) if (code - 0x306F).is_multiple_of(3) => code + 2,
+        _ => return None,
+    };
+
+    char::from_u32(voiced)
+}
+
+// Returns every normalized form a bookmark tag can be found by, including its romanized and hiragana readings
+pub fn search_keys(id: &str, name: Option<&str>, translations: &BookmarkTagTranslations) -> Vec<String> {
+    let mut texts = vec![id.to_string()];
+
+    texts.extend(name.map(|name| name.to_string()));
+    texts.extend(
+        [&translations.en, &translations.ko, &translations.zh, &translations.zh_tw, &translations.romaji].into_iter().flatten().cloned(),
+    );
+
+    // This is what lets romaji and kana match tags written in kanji
+    if is_japanese(id) != IsJapanese::False {
+        let converted = convert(normalize(id));
+        texts.push(converted.romaji);
+        texts.push(converted.hiragana);
+    }
+
+    let mut keys = vec![];
+
+    for text in texts {
+        let key = normalize(&text);
+
+        if !key.is_empty() && !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    keys
+}
+
+// The Levenshtein distance between two strings by character
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_full_width_ascii() {
+        assert_eq!(normalize("ＧＥＮＳＨＩＮ１２３"), "genshin123");
+    }
+
+    #[test]
+    fn normalizes_katakana_to_hiragana() {
+        assert_eq!(normalize("ホロライブ"), "ほろらいぶ");
+        assert_eq!(normalize("ヴァ"), "ゔぁ");
+    }
+
+    #[test]
+    fn normalizes_half_width_katakana() {
+        assert_eq!(normalize("ﾎﾛﾗｲﾌﾞ"), "ほろらいぶ");
+        assert_eq!(normalize("ﾊﾟﾝ"), "ぱん");
+        assert_eq!(normalize("ｳﾞ"), "ゔ");
+    }
+
+    #[test]
+    fn drops_dakuten_that_cannot_be_combined() {
+        assert_eq!(normalize("ｱﾞ"), "あ");
+        assert_eq!(normalize("ﾟ"), "");
+    }
+
+    #[test]
+    fn removes_separators() {
+        assert_eq!(normalize("Genshin Impact"), "genshinimpact");
+        assert_eq!(normalize("genshin_impact"), "genshinimpact");
+        assert_eq!(normalize("ブルー・アーカイブ"), "ぶるーあーかいぶ");
+        assert_eq!(normalize("ブルー　アーカイブ"), "ぶるーあーかいぶ");
+    }
+
+    #[test]
+    fn adds_dakuten() {
+        assert_eq!(add_dakuten('か', false), Some('が'));
+        assert_eq!(add_dakuten('ち', false), Some('ぢ'));
+        assert_eq!(add_dakuten('つ', false), Some('づ'));
+        assert_eq!(add_dakuten('と', false), Some('ど'));
+        assert_eq!(add_dakuten('は', false), Some('ば'));
+        assert_eq!(add_dakuten('ほ', true), Some('ぽ'));
+        assert_eq!(add_dakuten('う', false), Some('ゔ'));
+    }
+
+    #[test]
+    fn does_not_add_dakuten_to_voiced_or_other_kana() {
+        assert_eq!(add_dakuten('が', false), None);
+        assert_eq!(add_dakuten('っ', false), None);
+        assert_eq!(add_dakuten('か', true), None);
+        assert_eq!(add_dakuten('あ', false), None);
+    }
+
+    #[test]
+    fn measures_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("genshin", "genshin"), 0);
+        assert_eq!(edit_distance("genshin", "genhsin"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("ほろらいぶ", "ほろらいふ"), 1);
+    }
+
+    #[test]
+    fn includes_readings_of_japanese_tags() {
+        let keys = search_keys("東方", Some("touhou_project"), &BookmarkTagTranslations::default());
+
+        assert!(keys.contains(&"東方".to_string()));
+        assert!(keys.contains(&"touhouproject".to_string()));
+        assert!(keys.contains(&"とうほう".to_string()));
+    }
+
+    #[test]
+    fn deduplicates_search_keys() {
+        let translations = BookmarkTagTranslations { en: Some("Genshin Impact".into()), ..Default::default() };
+        assert_eq!(search_keys("genshin_impact", Some("genshin_impact"), &translations), vec!["genshinimpact"]);
+    }
+}
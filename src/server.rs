@@ -0,0 +1,299 @@
+use crate::{CONFIG, SHUTDOWN};
+use anyhow::{Context, Result};
+use axum::{
+    Router,
+    extract::connect_info::Connected,
+    http::{
+        HeaderValue, Method, StatusCode,
+        header::{AUTHORIZATION, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, RETRY_AFTER},
+    },
+    serve::{IncomingStream, Listener, serve},
+};
+use serde::Deserialize;
+use std::{
+    fs::remove_file,
+    future::pending,
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpStream, UnixListener},
+    signal::{
+        ctrl_c,
+        unix::{SignalKind, signal},
+    },
+    spawn,
+    sync::mpsc::{Receiver, channel},
+    time::{sleep, timeout},
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        crypto::ring::default_provider,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    },
+    server::TlsStream,
+};
+use tower_http::{
+    compression::CompressionLayer,
+    cors::{AllowOrigin, CorsLayer},
+    timeout::TimeoutLayer,
+};
+use tracing::{debug, error, info};
+
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const ACCEPT_ERROR_COOLDOWN: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize, PartialEq, Debug)]
+pub struct Server {
+    #[serde(default = "Server::default_bind")]
+    pub bind: String,
+
+    #[serde(default = "Server::default_port")]
+    pub port: u16,
+
+    // Listens on this Unix socket instead of the address and port
+    #[serde(default)]
+    pub unix_socket: Option<PathBuf>,
+
+    // Origins that can call the API from browsers, or "*" for any
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+
+    // Any of "gzip", "br" and "zstd"
+    #[serde(default = "Server::default_compression")]
+    pub compression: Vec<String>,
+
+    #[serde(default = "Server::default_request_timeout_seconds")]
+    pub request_timeout_seconds: u64,
+
+    // Serves HTTPS if both are set. These are PEM files
+    #[serde(default)]
+    pub tls_cert: Option<PathBuf>,
+
+    #[serde(default)]
+    pub tls_key: Option<PathBuf>,
+}
+
+impl Server {
+    fn default_bind() -> String {
+        "0.0.0.0".into()
+    }
+
+    fn default_port() -> u16 {
+        3000
+    }
+
+    fn default_compression() -> Vec<String> {
+        vec!["gzip".into(), "br".into(), "zstd".into()]
+    }
+
+    fn default_request_timeout_seconds() -> u64 {
+        30
+    }
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self {
+            bind: Self::default_bind(),
+            port: Self::default_port(),
+            unix_socket: None,
+            cors_origins: vec![],
+            compression: Self::default_compression(),
+            request_timeout_seconds: Self::default_request_timeout_seconds(),
+            tls_cert: None,
+            tls_key: None,
+        }
+    }
+}
+
+// The client address, which isn't known for Unix sockets
+#[derive(Clone, Copy, Debug)]
+pub struct ClientAddr(pub Option<SocketAddr>);
+
+impl Connected<IncomingStream<'_, TcpListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        Self(Some(*stream.remote_addr()))
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        Self(Some(*stream.remote_addr()))
+    }
+}
+
+impl Connected<IncomingStream<'_, UnixListener>> for ClientAddr {
+    fn connect_info(_stream: IncomingStream<'_, UnixListener>) -> Self {
+        Self(None)
+    }
+}
+
+// Handshakes are done in the background so slow clients don't hold up accepting others
+pub struct TlsListener {
+    connections: Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    fn new(listener: TcpListener, acceptor: TlsAcceptor) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (sender, connections) = channel(64);
+
+        spawn(async move {
+            loop {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(error) => {
+                        error!("An error occurred while trying to accept a connection: {error:?}");
+                        sleep(ACCEPT_ERROR_COOLDOWN).await;
+                        continue;
+                    },
+                };
+
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+
+                spawn(async move {
+                    match timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send((stream, addr)).await;
+                        },
+                        Ok(Err(error)) => debug!("TLS handshake with {addr} failed: {error:?}"),
+                        Err(_) => debug!("TLS handshake with {addr} timed out."),
+                    }
+                });
+            }
+        });
+
+        Ok(Self { connections, local_addr })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            None => pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+// Adds CORS, compression and the request timeout
+pub fn with_middleware(app: Router) -> Router {
+    let config = CONFIG.load();
+    let server = &config.server;
+
+    let allow_origin = match server.cors_origins.iter().any(|origin| origin == "*") {
+        true => AllowOrigin::any(),
+        false => AllowOrigin::list(server.cors_origins.iter().filter_map(|origin| HeaderValue::from_str(origin).ok())),
+    };
+
+    let cors = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+        .allow_headers([AUTHORIZATION, CONTENT_TYPE, IF_NONE_MATCH, IF_MODIFIED_SINCE])
+        .expose_headers([ETAG, RETRY_AFTER]);
+
+    let compression = CompressionLayer::new()
+        .gzip(server.compression.iter().any(|algorithm| algorithm == "gzip"))
+        .br(server.compression.iter().any(|algorithm| algorithm == "br"))
+        .zstd(server.compression.iter().any(|algorithm| algorithm == "zstd"));
+
+    app.layer(TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, Duration::from_secs(server.request_timeout_seconds)))
+        .layer(compression)
+        .layer(cors)
+}
+
+pub async fn serve_app(app: Router) -> Result<()> {
+    let config = CONFIG.load();
+    let server = &config.server;
+
+    if let Some(unix_socket) = &server.unix_socket {
+        // A socket left over from an unclean exit would make binding fail
+        if unix_socket.exists() {
+            remove_file(unix_socket).with_context(|| format!("Could not remove {}", unix_socket.display()))?;
+        }
+
+        let listener = UnixListener::bind(unix_socket).with_context(|| format!("Could not bind to {}", unix_socket.display()))?;
+        info!("Listening on {}.", unix_socket.display());
+
+        return serve_on(listener, app).await;
+    }
+
+    let addr = format!("{}:{}", server.bind, server.port);
+    let listener = TcpListener::bind(&addr).await.with_context(|| format!("Could not bind to {addr}"))?;
+
+    match (&server.tls_cert, &server.tls_key) {
+        (Some(tls_cert), Some(tls_key)) => {
+            let acceptor = load_tls_acceptor(tls_cert, tls_key)?;
+            info!("Listening on https://{addr}.");
+            serve_on(TlsListener::new(listener, acceptor)?, app).await
+        },
+        _ => {
+            info!("Listening on http://{addr}.");
+            serve_on(listener, app).await
+        },
+    }
+}
+
+async fn serve_on<L: Listener>(listener: L, app: Router) -> Result<()>
+where
+    L::Addr: std::fmt::Debug,
+    for<'a> ClientAddr: Connected<IncomingStream<'a, L>>,
+{
+    // The client addresses are used for rate limiting
+    serve(listener, app.into_make_service_with_connect_info::<ClientAddr>()).with_graceful_shutdown(shutdown_signal()).await?;
+    Ok(())
+}
+
+fn load_tls_acceptor(tls_cert: &Path, tls_key: &Path) -> Result<TlsAcceptor> {
+    let certs = CertificateDer::pem_file_iter(tls_cert)
+        .with_context(|| format!("Could not read {}", tls_cert.display()))?
+        .collect::<Result<Vec<CertificateDer>, _>>()
+        .with_context(|| format!("Could not parse {}", tls_cert.display()))?;
+    let key = PrivateKeyDer::from_pem_file(tls_key).with_context(|| format!("Could not read {}", tls_key.display()))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+// Resolves on Ctrl+C or SIGTERM, and tells the background tasks to stop
+async fn shutdown_signal() {
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            },
+            Err(error) => {
+                error!("An error occurred while trying to listen for SIGTERM: {error:?}");
+                pending::<()>().await;
+            },
+        }
+    };
+
+    tokio::select! {
+        _ = ctrl_c() => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutting down...");
+    SHUTDOWN.cancel();
+}
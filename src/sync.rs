@@ -1,17 +1,41 @@
 use crate::{
-    MONGODB,
-    mongodb::MongoDB,
-    pixiv::{PIXIV_BOOKMARKS_PER_PAGE, PixivBookmarks, PixivTags, PixivTagsBodyTagTranslationWrapper},
-    routes::bookmarks::PaginationSort,
+    HEALTH, METRICS, MONGODB, RESPONSE_CACHE, SHUTDOWN,
+    mongodb::{BookmarkOrder, MongoDB},
+    pixiv::{
+        PIXIV_BOOKMARKS_PER_PAGE, PixivArtworkState, PixivBookmarks, PixivIllust, PixivTags, PixivTagsBodyBreadcrumbs,
+        PixivTagsBodyTagTranslationWrapper, PixivUserTags,
+    },
+    rules::apply_user_tag_rules,
 };
 use anyhow::{Result, anyhow};
+use chrono::{TimeDelta, Utc};
 use kakasi::{IsJapanese, convert, is_japanese};
-use std::{fmt::Display, thread::sleep, time::Duration};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tokio::time::{sleep, timeout};
 use tracing::{error, info};
 
 const SYNC_BOOKMARKS_COOLDOWN: Duration = Duration::from_secs(10);
 const INSERT_ALL_BOOKMARKS_COOLDOWN: Duration = Duration::from_millis(500);
-const SYNC_BOOKMARK_TAG_TRANSLATIONS_COOLDOWN: Duration = Duration::from_millis(500);
+const TAG_TRANSLATION_COOLDOWN: Duration = Duration::from_secs(1);
+const TAG_TRANSLATION_QUEUE_IDLE_COOLDOWN: Duration = Duration::from_secs(5);
+const TAG_TRANSLATION_CACHE_INVALIDATION_INTERVAL: Duration = Duration::from_secs(60);
+const SYNC_USER_TAGS_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+const SYNC_USER_TAGS_PAGE_COOLDOWN: Duration = Duration::from_millis(500);
+const VALIDATE_BOOKMARKS_BATCH_SIZE: i64 = 50;
+const VALIDATE_BOOKMARK_COOLDOWN: Duration = Duration::from_secs(1);
+const VALIDATE_BOOKMARKS_IDLE_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+// How long a validated bookmark is left alone before it's checked again
+const VALIDATE_BOOKMARKS_INTERVAL_DAYS: i64 = 7;
+
+// How long a bookmark that failed to validate is left alone before it's retried
+const VALIDATE_BOOKMARK_RETRY_HOURS: i64 = 24;
+
+// pixiv lists bookmarks without any user tag under this tag
+const UNCATEGORIZED_USER_TAG: &str = "未分類";
 
 pub async fn sync_bookmarks() -> Result<()> {
     let mongodb = MONGODB.get().unwrap();
@@ -19,7 +43,16 @@ pub async fn sync_bookmarks() -> Result<()> {
 
     if bookmark_count == 0 {
         info!("Local database has no bookmarks. Inserting all bookmarks...");
-        insert_all_bookmarks(mongodb).await?;
+
+        HEALTH.set_importing(true);
+        let result = insert_all_bookmarks(mongodb).await;
+        HEALTH.set_importing(false);
+        result?;
+
+        if SHUTDOWN.is_cancelled() {
+            return Ok(());
+        }
+
         info!("Done inserting all bookmarks.");
     }
 
@@ -31,6 +64,10 @@ pub async fn sync_bookmarks() -> Result<()> {
         let mut recent_pixiv_bookmark_ids = vec![];
         let mut new_bookmarks = vec![];
 
+        // Only iterations without any errors count as a successful sync
+        let mut succeeded = true;
+        METRICS.sync_iterations.inc();
+
         while next_page {
             if page != 1 {
                 info!("Checking page {page}... This may happen if bookmarks weren't synced in a while.");
@@ -40,10 +77,13 @@ pub async fn sync_bookmarks() -> Result<()> {
                 Ok(bookmarks) => bookmarks,
                 Err(error) => {
                     error!("An error occurred while trying to get bookmark page {page}: {error:?}");
+                    succeeded = false;
                     break;
                 },
             };
 
+            METRICS.sync_pages_scanned.inc();
+
             if page == 1 {
                 recent_pixiv_bookmark_ids.extend(bookmarks.body.works.iter().map(|bookmark| bookmark.id.clone()));
             }
@@ -68,6 +108,7 @@ pub async fn sync_bookmarks() -> Result<()> {
                     Err(error) => {
                         // Since it errored, let's just break the loop and let it check from the newest page again
                         error!("An error occurred while trying to get bookmark {bookmark_id}: {error:?}");
+                        succeeded = false;
                         next_page = false;
                         break;
                     },
@@ -75,6 +116,12 @@ pub async fn sync_bookmarks() -> Result<()> {
             }
 
             if next_page {
+                // Stop between pages on shutdown. Nothing is inserted, since the next sync would stop at those bookmarks and skip the older pages
+                if SHUTDOWN.is_cancelled() {
+                    info!("Stopped syncing bookmarks at page {page}.");
+                    return Ok(());
+                }
+
                 page += 1;
             }
         }
@@ -84,8 +131,14 @@ pub async fn sync_bookmarks() -> Result<()> {
 
             if let Err(error) = mongodb.bookmarks.insert_many(new_bookmarks.clone()).await {
                 error!("An error occurred while trying to insert bookmarks: {error:?}");
+                succeeded = false;
             } else {
                 info!("{} new {} inserted: {}", ids.len(), if ids.len() == 1 { "bookmark" } else { "bookmarks" }, ids.join(", "));
+                RESPONSE_CACHE.invalidate();
+
+                if let Err(error) = apply_user_tag_rules(Some(&ids), false, true).await {
+                    error!("An error occurred while trying to apply user tag rules: {error:?}");
+                }
             }
         }
 
@@ -93,7 +146,7 @@ pub async fn sync_bookmarks() -> Result<()> {
         // This wouldn't be reliable if I removed some old bookmark that wasn't included in the list of recent ones, but whatever
         if !recent_pixiv_bookmark_ids.is_empty() {
             let recent_local_bookmarks =
-                match mongodb.bookmarks.find(None, 0, recent_pixiv_bookmark_ids.len() as i64, PaginationSort::Descending).await {
+                match mongodb.bookmarks.find(None, 0, recent_pixiv_bookmark_ids.len() as i64, BookmarkOrder::default(), None, None).await {
                     Ok(recent_bookmarks) => recent_bookmarks,
                     Err(error) => {
                         error!("An error occurred while trying to get bookmarks: {error:?}");
@@ -101,86 +154,325 @@ pub async fn sync_bookmarks() -> Result<()> {
                     },
                 };
 
-            let to_remove = recent_local_bookmarks.iter().filter(|bookmark| !recent_pixiv_bookmark_ids.contains(&bookmark.id));
+            let to_remove = recent_local_bookmarks.iter().filter(|(bookmark, _)| !recent_pixiv_bookmark_ids.contains(&bookmark.id));
 
-            for bookmark in to_remove {
+            for (bookmark, _) in to_remove {
                 if let Err(error) = mongodb.bookmarks.delete(&bookmark.id).await {
                     error!("An error occurred while trying to delete bookmark {}: {error:?}", bookmark.id);
+                    succeeded = false;
                 } else {
                     info!("Deleted bookmark {} because it was removed from recents.", bookmark.id);
+                    RESPONSE_CACHE.invalidate();
                 }
             }
         }
 
-        sleep(SYNC_BOOKMARKS_COOLDOWN);
+        if succeeded {
+            METRICS.last_successful_sync.set(Utc::now().timestamp());
+        }
+
+        if timeout(SYNC_BOOKMARKS_COOLDOWN, SHUTDOWN.cancelled()).await.is_ok() {
+            info!("Stopped syncing bookmarks.");
+            return Ok(());
+        }
     }
 }
 
+pub async fn sync_user_tags() -> Result<()> {
+    let mongodb = MONGODB.get().unwrap();
+
+    loop {
+        info!("Syncing user tags...");
+
+        match get_all_user_tags().await {
+            Ok(user_tags) => {
+                // Group the bookmarks by their set of user tags so each group only needs a single update
+                let mut groups = HashMap::<Vec<String>, Vec<String>>::new();
+
+                for (id, mut tags) in user_tags {
+                    tags.sort();
+                    groups.entry(tags).or_default().push(id);
+                }
+
+                let tagged_ids = groups.values().flatten().cloned().collect::<Vec<String>>();
+
+                if let Err(error) = mongodb.bookmarks.clear_user_tags_except(&tagged_ids).await {
+                    error!("An error occurred while trying to clear user tags: {error:?}");
+                }
+
+                for (tags, ids) in &groups {
+                    if let Err(error) = mongodb.bookmarks.set_user_tags(ids, tags).await {
+                        error!("An error occurred while trying to set user tags {tags:?}: {error:?}");
+                    }
+                }
+
+                RESPONSE_CACHE.invalidate();
+                info!("Done syncing user tags for {} {}.", tagged_ids.len(), if tagged_ids.len() == 1 { "bookmark" } else { "bookmarks" });
+            },
+            Err(error) => error!("An error occurred while trying to get user tags: {error:?}"),
+        }
+
+        sleep(SYNC_USER_TAGS_COOLDOWN).await;
+    }
+}
+
+// Returns the user tags of every public bookmark that has any, keyed by artwork ID
+async fn get_all_user_tags() -> Result<HashMap<String, Vec<String>>> {
+    let mut user_tags = HashMap::<String, Vec<String>>::new();
+
+    for user_tag in PixivUserTags::get().await?.body.public {
+        if user_tag.tag == UNCATEGORIZED_USER_TAG {
+            continue;
+        }
+
+        let total_pages = ((user_tag.cnt as f64) / (PIXIV_BOOKMARKS_PER_PAGE as f64)).ceil() as i64;
+
+        for page in 1..=total_pages {
+            let bookmarks = PixivBookmarks::get_page(page, &user_tag.tag).await?;
+
+            for bookmark in bookmarks.body.works {
+                user_tags.entry(bookmark.id).or_default().push(user_tag.tag.clone());
+            }
+
+            sleep(SYNC_USER_TAGS_PAGE_COOLDOWN).await;
+        }
+    }
+
+    Ok(user_tags)
+}
+
 pub async fn insert_all_bookmarks(mongodb: &MongoDB) -> Result<()> {
     let first_page = PixivBookmarks::get_page(1, "").await?;
     let total_pages = ((first_page.body.total as f64) / (PIXIV_BOOKMARKS_PER_PAGE as f64)).ceil() as i64;
     let mut page = total_pages;
 
     while page != 1 {
+        // The pages are inserted from the oldest, so the regular sync can pick up the rest from the newest
+        if SHUTDOWN.is_cancelled() {
+            info!("Stopped inserting all bookmarks at page {page}/{total_pages}.");
+            return Ok(());
+        }
+
         info!("Inserting page {page}/{total_pages}...");
 
         let bookmarks = PixivBookmarks::get_page(page, "").await?;
         mongodb.bookmarks.insert_many(bookmarks.body.works).await?;
+        HEALTH.record_import_progress();
 
         page -= 1;
-        sleep(INSERT_ALL_BOOKMARKS_COOLDOWN);
+        sleep(INSERT_ALL_BOOKMARKS_COOLDOWN).await;
     }
 
     mongodb.bookmarks.insert_many(first_page.body.works).await?;
+    HEALTH.record_import_progress();
+    RESPONSE_CACHE.invalidate();
 
     Ok(())
 }
 
-pub async fn sync_bookmark_tag_translations<T: Display>(tags: Vec<T>) -> Result<()> {
+// Stores the relations from the tag breadcrumbs in the tag graph
+async fn sync_tag_graph(id: &str, breadcrumbs: &PixivTagsBodyBreadcrumbs) -> Result<()> {
     let mongodb = MONGODB.get().unwrap();
 
-    for tag in tags {
-        let id = tag.to_string().to_lowercase();
+    // The ancestors are ordered from the top, so each one is the parent of the next (and the last one is the parent of the current tag)
+    let ancestors = breadcrumbs.current.iter().map(|entry| entry.tag.to_lowercase()).filter(|tag| tag != id).collect::<Vec<String>>();
 
-        let Some(bookmark_tag) = mongodb.bookmarks.tags.get(&id).await? else { continue };
+    for (parent, child) in ancestors.iter().zip(ancestors.iter().skip(1).chain([&id.to_string()])) {
+        mongodb.tag_graph.add_child(parent, child).await?;
+    }
+
+    // Successors with the same English name as the current tag are translations of it rather than child tags
+    let current_en = breadcrumbs.successor.iter().find(|entry| entry.tag.to_lowercase() == id).map(|entry| entry.translation.en.as_str());
 
-        if bookmark_tag.name.is_some() {
+    for entry in &breadcrumbs.successor {
+        if entry.tag.to_lowercase() == id {
             continue;
         }
 
-        let pixiv_tags = match PixivTags::search(&id).await {
-            Ok(pixiv_tags) => pixiv_tags,
+        let en = simplify_tag(&entry.translation.en);
+
+        if en == simplify_tag(id) || current_en.is_some_and(|current_en| en == simplify_tag(current_en)) {
+            mongodb.tag_graph.add_synonym(id, &entry.tag).await?;
+        } else {
+            mongodb.tag_graph.add_child(id, &entry.tag).await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Strips everything but letters and numbers so differently formatted names of the same tag can be compared
+fn simplify_tag(tag: &str) -> String {
+    tag.chars().filter(|char| char.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+// Adds search keys to the tags that don't have them yet, which are the ones from before autocompletion existed
+pub async fn index_bookmark_tags() -> Result<()> {
+    let mongodb = MONGODB.get().unwrap();
+    let count =
+        mongodb.bookmarks.tags.index_missing_search_keys().await.map_err(|error| anyhow!("Failed to index bookmark tags: {error:?}"))?;
+
+    if count != 0 {
+        info!("Indexed {count} bookmark {} for autocompletion.", if count == 1 { "tag" } else { "tags" });
+        RESPONSE_CACHE.invalidate();
+    }
+
+    Ok(())
+}
+
+// Sweeps the whole collection in batches, starting from the bookmarks that were never validated
+pub async fn validate_bookmarks() -> Result<()> {
+    let mongodb = MONGODB.get().unwrap();
+
+    loop {
+        let since = (Utc::now() - TimeDelta::days(VALIDATE_BOOKMARKS_INTERVAL_DAYS)).to_rfc3339();
+        let retry_since = (Utc::now() - TimeDelta::hours(VALIDATE_BOOKMARK_RETRY_HOURS)).to_rfc3339();
+
+        let ids = match mongodb.bookmarks.find_unvalidated_ids(&since, &retry_since, VALIDATE_BOOKMARKS_BATCH_SIZE).await {
+            Ok(ids) => ids,
             Err(error) => {
-                error!(r#"An error occurred while trying to get pixiv tag "{id}": {error:?}"#);
+                error!("An error occurred while trying to get bookmarks to validate: {error:?}");
+                sleep(VALIDATE_BOOKMARKS_IDLE_COOLDOWN).await;
                 continue;
             },
         };
 
-        // Add all related tags (which also sometimes include the translated version of the current tag)
-        for pixiv_tag in &pixiv_tags.body.breadcrumbs.successor {
-            let new_name = pixiv_tag.translation.en.split_whitespace().collect::<Vec<&str>>().join("_");
-            mongodb.bookmarks.tags.set_name(&pixiv_tag.tag, new_name).await?;
+        if ids.is_empty() {
+            sleep(VALIDATE_BOOKMARKS_IDLE_COOLDOWN).await;
+            continue;
         }
 
-        // Add the romanized version of the current tag if it wasn't included in the breadcrumbs
-        if !pixiv_tags.body.breadcrumbs.successor.iter().any(|entry| entry.tag.to_lowercase() == id) {
-            let mut new_name = id.clone();
+        info!("Validating {} {}...", ids.len(), if ids.len() == 1 { "bookmark" } else { "bookmarks" });
 
-            if let PixivTagsBodyTagTranslationWrapper::HashMap(hashmap) = pixiv_tags.body.tag_translation {
-                let romaji = hashmap.into_iter().find(|entry| entry.0.to_lowercase() == id).map(|entry| entry.1.romaji);
+        for id in ids {
+            if let Err(error) = validate_bookmark(&id).await {
+                error!("An error occurred while trying to validate bookmark {id}: {error:?}");
 
-                if let Some(romaji) = romaji {
-                    new_name = romaji;
-                } else if is_japanese(&id) == IsJapanese::True {
-                    new_name = convert(&id).romaji.split_whitespace().collect::<Vec<&str>>().join("_");
+                if let Err(error) = mongodb.bookmarks.set_validation_attempt(&id).await {
+                    error!("An error occurred while trying to record the validation attempt of bookmark {id}: {error:?}");
                 }
             }
 
-            mongodb.bookmarks.tags.set_name(&id, new_name).await?;
+            sleep(VALIDATE_BOOKMARK_COOLDOWN).await;
         }
+    }
+}
 
-        sleep(SYNC_BOOKMARK_TAG_TRANSLATIONS_COOLDOWN);
+// Checks a mirrored artwork on pixiv and records its state. Deleted artworks are removed from the local database
+pub async fn validate_bookmark(id: &str) -> Result<PixivArtworkState> {
+    let mongodb = MONGODB.get().unwrap();
+    let state = PixivIllust::get_state(id).await?;
+
+    // The cache is only invalidated if something changed, as the background sweep validates a bookmark every second
+    if state == PixivArtworkState::Deleted {
+        info!("Bookmark {id} exists in the local database but was deleted on pixiv. Deleting...");
+        mongodb.bookmarks.delete(id).await?;
+        RESPONSE_CACHE.invalidate();
+    } else if mongodb.bookmarks.set_validation_state(id, state).await? {
+        RESPONSE_CACHE.invalidate();
     }
 
+    Ok(state)
+}
+
+// Translates the queued tags one at a time, which keeps the rate of pixiv tag searches in check
+pub async fn process_tag_translation_queue() -> Result<()> {
+    let mongodb = MONGODB.get().unwrap();
+
+    // Translations are only made visible once the queue is empty or every so often, instead of flushing the cache for every tag
+    let mut translated = false;
+    let mut invalidated_at = Instant::now();
+
+    loop {
+        let next_job = mongodb.bookmarks.tags.queue.next().await;
+
+        if translated && (matches!(next_job, Ok(None)) || invalidated_at.elapsed() >= TAG_TRANSLATION_CACHE_INVALIDATION_INTERVAL) {
+            RESPONSE_CACHE.invalidate();
+            translated = false;
+            invalidated_at = Instant::now();
+        }
+
+        let job = match next_job {
+            Ok(Some(job)) => job,
+            Ok(None) => {
+                sleep(TAG_TRANSLATION_QUEUE_IDLE_COOLDOWN).await;
+                continue;
+            },
+            Err(error) => {
+                error!("An error occurred while trying to get the next tag translation job: {error:?}");
+                sleep(TAG_TRANSLATION_QUEUE_IDLE_COOLDOWN).await;
+                continue;
+            },
+        };
+
+        let result = match translate_bookmark_tag(&job.id).await {
+            Ok(()) => {
+                translated = true;
+                mongodb.bookmarks.tags.queue.complete(&job.id).await
+            },
+            Err(error) => {
+                error!(r#"An error occurred while trying to translate tag "{}" (attempt {}): {error:?}"#, job.id, job.attempts + 1);
+                mongodb.bookmarks.tags.queue.fail(&job, format!("{error:#}")).await
+            },
+        };
+
+        if let Err(error) = result {
+            error!(r#"An error occurred while trying to update the tag translation job for "{}": {error:?}"#, job.id);
+        }
+
+        sleep(TAG_TRANSLATION_COOLDOWN).await;
+    }
+}
+
+async fn translate_bookmark_tag(id: &str) -> Result<()> {
+    let mongodb = MONGODB.get().unwrap();
+    let id = id.to_lowercase();
+
+    let Some(bookmark_tag) = mongodb.bookmarks.tags.get(&id).await? else { return Ok(()) };
+
+    if bookmark_tag.translated_at.is_some() {
+        return Ok(());
+    }
+
+    let pixiv_tags = PixivTags::search(&id).await.map_err(|error| anyhow!("Failed to get pixiv tag: {error:?}"))?;
+
+    // Add all related tags (which also sometimes include the translated version of the current tag)
+    for pixiv_tag in &pixiv_tags.body.breadcrumbs.successor {
+        let new_name = pixiv_tag.translation.en.split_whitespace().collect::<Vec<&str>>().join("_");
+        mongodb.bookmarks.tags.set_name(&pixiv_tag.tag, new_name).await?;
+        mongodb.bookmarks.tags.set_translations(&pixiv_tag.tag, &(&pixiv_tag.translation).into()).await?;
+    }
+
+    sync_tag_graph(&id, &pixiv_tags.body.breadcrumbs).await?;
+
+    let tag_translations = match pixiv_tags.body.tag_translation {
+        PixivTagsBodyTagTranslationWrapper::HashMap(hashmap) => hashmap,
+        PixivTagsBodyTagTranslationWrapper::AnEmptyArray() => HashMap::new(),
+    };
+
+    // The tag translations include the current tag and some of the related tags
+    for (pixiv_tag, translation) in &tag_translations {
+        mongodb.bookmarks.tags.set_translations(pixiv_tag, &translation.into()).await?;
+    }
+
+    // Add the romanized version of the current tag if it wasn't included in the breadcrumbs
+    if !pixiv_tags.body.breadcrumbs.successor.iter().any(|entry| entry.tag.to_lowercase() == id) {
+        let mut new_name = id.clone();
+
+        if !tag_translations.is_empty() {
+            let romaji = tag_translations.into_iter().find(|entry| entry.0.to_lowercase() == id).and_then(|entry| entry.1.romaji);
+
+            if let Some(romaji) = romaji {
+                new_name = romaji;
+            } else if is_japanese(&id) == IsJapanese::True {
+                new_name = convert(&id).romaji.split_whitespace().collect::<Vec<&str>>().join("_");
+            }
+        }
+
+        mongodb.bookmarks.tags.set_name(&id, new_name).await?;
+    }
+
+    mongodb.bookmarks.tags.set_translated(&id).await?;
+
     Ok(())
 }